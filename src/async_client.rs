@@ -0,0 +1,352 @@
+//! An asynchronous client for the Broadcasting API, built on `tokio`.
+//!
+//! Incoming messages are exposed as a [`Stream`], with the session [`Context`] updated before
+//! each message is yielded. Events derived by the context are queued until collected with
+//! [`AsyncBroadcastingClient::take_events`].
+//!
+//! This module is only available with the `tokio` feature enabled.
+
+use crate::client::{
+    handshake_reply, validate_camera, validate_hud_page, ClientError, HandshakeConfig,
+    HandshakeReply, UDP_MAX,
+};
+use crate::protocol::inbound::{InboundMessage, RegistrationResult};
+use crate::protocol::outbound::{
+    EntrylistRequest, FocusRequest, HudPageRequest, InstantReplayRequest, OutboundMessage,
+    RegistrationRequest, TrackDataRequest, UnregisterRequest,
+};
+use crate::protocol::DecodeMode;
+use crate::session::{Context, SessionEvent};
+use futures_core::Stream;
+use log::{debug, warn};
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use tokio::io::ReadBuf;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::time::{sleep_until, timeout_at, Instant};
+
+pub struct AsyncBroadcastingClient {
+    connection_id: u32,
+    read_only: bool,
+    socket: UdpSocket,
+    context: Context,
+    stopped: bool,
+    buffer: Vec<u8>,
+    decode_mode: DecodeMode,
+    events: Vec<SessionEvent>,
+}
+
+impl AsyncBroadcastingClient {
+    /// Connects to the simulator using the default [`HandshakeConfig`].
+    pub async fn connect<A: ToSocketAddrs, B: ToSocketAddrs>(
+        listen: A,
+        remote: B,
+        req: RegistrationRequest<'_>,
+    ) -> Result<Self, ClientError> {
+        Self::connect_with(listen, remote, req, HandshakeConfig::default()).await
+    }
+
+    /// Connects to the simulator, registering according to `config`.
+    pub async fn connect_with<A: ToSocketAddrs, B: ToSocketAddrs>(
+        listen: A,
+        remote: B,
+        req: RegistrationRequest<'_>,
+        config: HandshakeConfig,
+    ) -> Result<Self, ClientError> {
+        let socket = UdpSocket::bind(listen).await?;
+        socket.connect(remote).await?;
+
+        let requests_commands = req.requests_commands();
+        let mut registration = vec![];
+        req.encode(&mut registration)?;
+
+        let mut incoming = vec![0u8; UDP_MAX];
+        let result = handshake(
+            &socket,
+            &registration,
+            &mut incoming,
+            requests_commands,
+            &config,
+        )
+        .await?;
+        let connection_id = result.connection_id;
+
+        Ok(Self {
+            connection_id,
+            read_only: result.read_only,
+            socket,
+            context: Context::new(),
+            stopped: false,
+            buffer: incoming,
+            decode_mode: DecodeMode::Lenient,
+            events: vec![],
+        })
+    }
+
+    pub async fn send<M>(&self, message: M) -> Result<(), std::io::Error>
+    where
+        M: OutboundMessage<Vec<u8>>,
+    {
+        let mut buffer = Vec::with_capacity(64);
+        message.encode(&mut buffer)?;
+        self.socket.send(&buffer).await?;
+
+        Ok(())
+    }
+
+    pub fn ctx(&self) -> &Context {
+        &self.context
+    }
+
+    /// Removes and returns the events derived from the messages yielded so far.
+    pub fn take_events(&mut self) -> Vec<SessionEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The connection ID assigned by the simulator during registration.
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    /// `true` if the simulator did not accept the command password, in which case the command
+    /// methods will return [`ClientError::ReadOnly`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// See [`BroadcastingClient::set_decode_mode`](crate::client::BroadcastingClient::set_decode_mode).
+    pub fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.decode_mode = mode;
+    }
+
+    /// See [`BroadcastingClient::request_entry_list`](crate::client::BroadcastingClient::request_entry_list).
+    pub async fn request_entry_list(&self) -> Result<(), ClientError> {
+        Ok(self.send(EntrylistRequest::new(self.connection_id)).await?)
+    }
+
+    /// See [`BroadcastingClient::request_track_data`](crate::client::BroadcastingClient::request_track_data).
+    pub async fn request_track_data(&self) -> Result<(), ClientError> {
+        Ok(self.send(TrackDataRequest::new(self.connection_id)).await?)
+    }
+
+    /// See [`BroadcastingClient::focus_car`](crate::client::BroadcastingClient::focus_car).
+    pub async fn focus_car(&self, car_id: u16) -> Result<(), ClientError> {
+        self.check_writable()?;
+        Ok(self
+            .send(FocusRequest::new(self.connection_id, Some(car_id), None))
+            .await?)
+    }
+
+    /// See [`BroadcastingClient::set_camera`](crate::client::BroadcastingClient::set_camera).
+    pub async fn set_camera(&self, camera_set: &str, camera: &str) -> Result<(), ClientError> {
+        self.check_writable()?;
+        validate_camera(&self.context, camera_set, camera)?;
+        Ok(self
+            .send(FocusRequest::new(
+                self.connection_id,
+                None,
+                Some((camera_set, camera)),
+            ))
+            .await?)
+    }
+
+    /// See [`BroadcastingClient::set_hud_page`](crate::client::BroadcastingClient::set_hud_page).
+    pub async fn set_hud_page(&self, hud_page: &str) -> Result<(), ClientError> {
+        self.check_writable()?;
+        validate_hud_page(&self.context, hud_page)?;
+        Ok(self
+            .send(HudPageRequest::new(self.connection_id, hud_page))
+            .await?)
+    }
+
+    /// See [`BroadcastingClient::instant_replay`](crate::client::BroadcastingClient::instant_replay).
+    pub async fn instant_replay(
+        &self,
+        start_time: f32,
+        duration_ms: f32,
+        car_id: Option<u16>,
+    ) -> Result<(), ClientError> {
+        self.check_writable()?;
+        Ok(self
+            .send(InstantReplayRequest::new(
+                self.connection_id,
+                start_time,
+                duration_ms,
+                car_id,
+                None,
+            ))
+            .await?)
+    }
+
+    fn check_writable(&self) -> Result<(), ClientError> {
+        if self.read_only {
+            Err(ClientError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends an [`UnregisterRequest`] to the simulator and destroys the client.
+    pub async fn shutdown(mut self) -> Result<(), std::io::Error> {
+        self.send(UnregisterRequest::new(self.connection_id))
+            .await?;
+        self.stopped = true;
+        Ok(())
+    }
+}
+
+async fn handshake(
+    socket: &UdpSocket,
+    registration: &[u8],
+    incoming: &mut [u8],
+    requests_commands: bool,
+    config: &HandshakeConfig,
+) -> Result<RegistrationResult<'static>, ClientError> {
+    for attempt in 1..=config.attempts {
+        debug!("Sending registration request, attempt {}", attempt);
+        socket.send(registration).await?;
+
+        let deadline = Instant::now() + config.timeout;
+        loop {
+            let size = match timeout_at(deadline, socket.recv(incoming)).await {
+                Ok(Ok(size)) => size,
+                Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+                    // Nothing is listening yet, wait out the rest of this attempt
+                    sleep_until(deadline).await;
+                    break;
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => break,
+            };
+
+            match handshake_reply(&incoming[..size], requests_commands, config)? {
+                HandshakeReply::Skip => {}
+                HandshakeReply::Registered(result) => return Ok(result),
+                HandshakeReply::Failed { error, unregister } => {
+                    if let Some(unregister) = unregister {
+                        socket.send(&unregister).await?;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    Err(ClientError::RegistrationTimeout(config.attempts))
+}
+
+impl Stream for AsyncBroadcastingClient {
+    type Item = Result<InboundMessage<'static>, ClientError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let context = &mut this.context;
+        let events = &mut this.events;
+        let mode = this.decode_mode;
+        let mut buf = ReadBuf::new(&mut this.buffer);
+        match this.socket.poll_recv(cx, &mut buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(Ok(())) => {
+                let decoded = InboundMessage::decode_with(buf.filled(), mode)
+                    .map(|msg| {
+                        events.extend(context.process_message(&msg));
+                        msg.into_owned()
+                    })
+                    .map_err(ClientError::MessageDecodeError);
+                Poll::Ready(Some(decoded))
+            }
+        }
+    }
+}
+
+impl Drop for AsyncBroadcastingClient {
+    fn drop(&mut self) {
+        if !self.stopped {
+            // We can't await in Drop, so make a best-effort attempt to unregister
+            let mut buffer = Vec::with_capacity(5);
+            let res = UnregisterRequest::new(self.connection_id)
+                .encode(&mut buffer)
+                .and_then(|_| self.socket.try_send(&buffer));
+            if let Err(e) = res {
+                warn!("Failed to send shutdown on Drop: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::poll_fn;
+
+    async fn next(client: &mut AsyncBroadcastingClient) -> InboundMessage<'static> {
+        poll_fn(|cx| Pin::new(&mut *client).poll_next(cx))
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn connected_pair() -> (AsyncBroadcastingClient, UdpSocket) {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let req = RegistrationRequest::new("Test", "asd", 250, "");
+
+        let (client, _) = tokio::join!(
+            AsyncBroadcastingClient::connect("127.0.0.1:0", server_addr, req),
+            async {
+                let mut buf = [0u8; 64];
+                let (_, peer) = server.recv_from(&mut buf).await.unwrap();
+                assert_eq!(buf[0], 0x01);
+                server.connect(peer).await.unwrap();
+                server
+                    .send(b"\x01\x07\x00\x00\x00\x01\x00\x00\x00")
+                    .await
+                    .unwrap();
+            }
+        );
+
+        (client.unwrap(), server)
+    }
+
+    #[tokio::test]
+    async fn connects_and_streams_messages() {
+        let (mut client, server) = connected_pair().await;
+        assert_eq!(client.connection_id(), 7);
+
+        server
+            .send(&[0x04, 0x07, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03])
+            .await
+            .unwrap();
+
+        let msg = next(&mut client).await;
+        assert!(matches!(msg, InboundMessage::EntrylistUpdate(_)));
+
+        let track = include_bytes!("../docs/pcap/track_data.bin");
+        server.send(track).await.unwrap();
+
+        let msg = next(&mut client).await;
+        assert!(matches!(msg, InboundMessage::TrackData(_)));
+        assert_eq!(client.ctx().track_data().unwrap().name, "Circuit Zolder");
+    }
+
+    #[tokio::test]
+    async fn sends_commands() {
+        let (client, server) = connected_pair().await;
+
+        client.request_entry_list().await.unwrap();
+        let mut buf = [0u8; 64];
+        let size = server.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..size], &[0x0a, 0x07, 0x00, 0x00, 0x00]);
+
+        assert!(matches!(
+            client.set_hud_page("Basic HUD").await,
+            Err(ClientError::MissingTrackData)
+        ));
+
+        client.shutdown().await.unwrap();
+        let size = server.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..size], &[0x09, 0x07, 0x00, 0x00, 0x00]);
+    }
+}
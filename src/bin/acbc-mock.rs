@@ -0,0 +1,85 @@
+//! Serves a recorded session to Broadcasting API clients, printing the commands they send.
+//!
+//! Usage: `acbc-mock <listen address> <capture> [password] [command password]`
+//!
+//! The capture may be an `acbc` recording or a pcap/pcapng file containing traffic on port 9000.
+
+use acbc::mock::{MockServer, MockServerConfig, Script};
+use acbc::pcap::CaptureReader;
+use acbc::replay::{PlaybackSpeed, Player};
+use std::io::ErrorKind;
+use std::process::exit;
+use std::time::Duration;
+
+const BROADCASTING_PORT: u16 = 9000;
+
+fn load_script(path: &str) -> std::io::Result<Script> {
+    match Player::open(path, PlaybackSpeed::Stepwise) {
+        Ok(player) => {
+            let datagrams = player
+                .map(|d| d.map(|d| d.payload))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Script::from_datagrams(datagrams))
+        }
+        Err(e) if e.kind() == ErrorKind::InvalidData => {
+            let datagrams = CaptureReader::open(path, BROADCASTING_PORT)?
+                .filter(|p| {
+                    p.as_ref()
+                        .map(|p| p.direction == acbc::pcap::Direction::Inbound)
+                        .unwrap_or(true)
+                })
+                .map(|p| p.map(|p| p.payload))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Script::from_datagrams(datagrams))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!(
+            "Usage: {} <listen address> <capture> [password] [command password]",
+            args[0]
+        );
+        exit(2);
+    }
+
+    let defaults = MockServerConfig::default();
+    let config = MockServerConfig {
+        password: args.get(3).cloned().unwrap_or(defaults.password),
+        command_password: args.get(4).cloned().unwrap_or(defaults.command_password),
+    };
+
+    let script = load_script(&args[2]).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {}", args[2], e);
+        exit(1);
+    });
+
+    let mut server = MockServer::bind(&args[1], config, script).unwrap_or_else(|e| {
+        eprintln!("Failed to bind {}: {}", args[1], e);
+        exit(1);
+    });
+    let commands = server.commands();
+    println!("Listening on {}", args[1]);
+
+    loop {
+        if let Err(e) = server.run_once(Duration::from_millis(100)) {
+            eprintln!("Server error: {}", e);
+            exit(1);
+        }
+        for command in commands.drain() {
+            match command.message() {
+                Ok(message) => println!(
+                    "{} (connection {}): {:?}",
+                    command.from, command.connection_id, message
+                ),
+                Err(_) => println!(
+                    "{} (connection {}): {:02x?}",
+                    command.from, command.connection_id, command.payload
+                ),
+            }
+        }
+    }
+}
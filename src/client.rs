@@ -1,19 +1,28 @@
 //! A simple, batteries-included client for the Broadcasting API
 
+use crate::protocol::acc_enum::SessionPhase;
 use crate::protocol::inbound::{
     BroadcastingEvent, EntrylistCar, EntrylistUpdate, InboundMessage, RealtimeCarUpdate,
-    RealtimeUpdate, TrackData,
+    RealtimeUpdate, RegistrationResult, TrackData,
 };
-use crate::protocol::outbound::{OutboundMessage, RegistrationRequest, UnregisterRequest};
-use crate::session::Context;
-use log::{debug, info, trace};
+use crate::protocol::outbound::{
+    EntrylistRequest, FocusRequest, HudPageRequest, InstantReplayRequest, OutboundMessage,
+    RegistrationRequest, TrackDataRequest, UnregisterRequest,
+};
+use crate::protocol::DecodeMode;
+use crate::replay::{PlaybackSpeed, Player, Recorder};
+use crate::session::{
+    ArchivedSession, Context, PitStop, SectorRating, SessionEvent, Stint, Weather,
+};
+use log::{debug, info, trace, warn};
 use nom_supreme::error::ErrorTree;
 use nom_supreme::final_parser::ByteOffset;
+use std::io::{ErrorKind, Read, Write};
 use std::net::{ToSocketAddrs, UdpSocket};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
-const UDP_MAX: usize = 65535;
+pub(crate) const UDP_MAX: usize = 65535;
 
 pub trait MessageHandler {
     fn realtime_update<H: MessageHandler>(
@@ -57,79 +66,233 @@ pub trait MessageHandler {
         _event: &BroadcastingEvent,
     ) {
     }
+
+    /// Called when a car enters the pit lane, having completed `lap` laps.
+    fn pit_entry<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _lap: u16,
+    ) {
+    }
+
+    /// Called when a car rejoins the track after a pit stop.
+    fn pit_exit<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _stop: &PitStop,
+    ) {
+    }
+
+    /// Called when a driver hands the car over to a team mate.
+    fn stint_finished<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _stint: &Stint,
+    ) {
+    }
+
+    /// Called when a car's overall position has changed since the previous session update.
+    fn position_changed<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _from: u16,
+        _to: u16,
+    ) {
+    }
+
+    /// Called when `car_id` passes `overtaken_car_id` during a race, both cars being on track.
+    fn overtake<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _overtaken_car_id: u16,
+    ) {
+    }
+
+    /// Called when a car comes to a halt on the track while the session is running.
+    fn car_stopped<H: MessageHandler>(&self, _client: &BroadcastingClient<H>, _car_id: u16) {}
+
+    /// Called when the driver in a car changes, `from` and `to` being indexes into its entry.
+    fn driver_swap<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _from: u16,
+        _to: u16,
+    ) {
+    }
+
+    fn session_phase_changed<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _from: SessionPhase,
+        _to: SessionPhase,
+    ) {
+    }
+
+    fn weather_changed<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _from: Weather,
+        _to: Weather,
+    ) {
+    }
+
+    /// Called when a car completes a sector, counting from 0.
+    fn sector_completed<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _car_id: u16,
+        _sector: usize,
+        _time: Duration,
+        _rating: SectorRating,
+    ) {
+    }
+
+    /// Called when a new session starts, with the state of the session which has just finished.
+    fn session_archived<H: MessageHandler>(
+        &self,
+        _client: &BroadcastingClient<H>,
+        _session: &ArchivedSession,
+    ) {
+    }
+
+    /// Called when no [`RealtimeUpdate`] has arrived within the client's liveness timeout.
+    fn disconnected<H: MessageHandler>(&self, _client: &BroadcastingClient<H>) {}
+
+    /// Called once the client has registered again after a disconnect. The entry list and track
+    /// data have already been re-requested at this point.
+    fn reconnected<H: MessageHandler>(&self, _client: &BroadcastingClient<H>) {}
 }
 
 pub struct BroadcastingClient<H: MessageHandler> {
     connection_id: u32,
-    socket: UdpSocket,
+    read_only: bool,
+    transport: Transport,
+    recorder: Option<Recorder<Box<dyn Write + Send>>>,
     context: Context,
     stopped: bool,
     handler: H,
+    // The encoded registration packet, kept so we can register again after a disconnect
+    registration: Vec<u8>,
+    // How registration replies are checked, both when connecting and when registering again
+    handshake: HandshakeConfig,
+    requests_commands: bool,
+    liveness_timeout: Option<Duration>,
+    last_activity: Instant,
+    connected: bool,
+    decode_mode: DecodeMode,
+}
+
+// Where the client's datagrams come from
+enum Transport {
+    Socket(UdpSocket),
+    Replay(Player<Box<dyn Read + Send>>),
+}
+
+/// Controls how the client registers with the simulator when connecting.
+#[derive(Debug, Clone)]
+pub struct HandshakeConfig {
+    /// How long to wait for a registration reply before sending the request again.
+    pub timeout: Duration,
+    /// The number of registration requests to send before giving up.
+    pub attempts: u32,
+    /// Accept a read-only registration even though a command password was supplied.
+    pub allow_read_only: bool,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(1),
+            attempts: 3,
+            allow_read_only: false,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum ClientError {
     #[error("Server returned registration error: {0}")]
     RegistrationError(String),
+    #[error("No registration reply received after {0} attempts")]
+    RegistrationTimeout(u32),
+    #[error("Server granted read-only access, the command password was not accepted")]
+    ReadOnlyGranted,
     #[error("Failed decoding packet at location {0:?}")]
     MessageDecodeError(ErrorTree<ByteOffset>),
     #[error("Socket error: {0}")]
     SocketError(#[from] std::io::Error),
+    #[error("Reached the end of the replayed session")]
+    EndOfReplay,
+    #[error("Client is registered read-only, commands will be ignored by the simulator")]
+    ReadOnly,
+    #[error("No track data has been received from the simulator yet")]
+    MissingTrackData,
+    #[error("Track does not offer camera `{1}` in camera set `{0}`")]
+    UnknownCamera(String, String),
+    #[error("Track does not offer HUD page `{0}`")]
+    UnknownHudPage(String),
 }
 
 impl<H> BroadcastingClient<H>
 where
     H: MessageHandler,
 {
+    /// Connects to the simulator using the default [`HandshakeConfig`].
     pub fn connect<A: ToSocketAddrs, B: ToSocketAddrs>(
         listen: A,
         remote: B,
         handler: H,
         req: RegistrationRequest,
+    ) -> Result<Self, ClientError> {
+        Self::connect_with(listen, remote, handler, req, HandshakeConfig::default())
+    }
+
+    /// Connects to the simulator, registering according to `config`.
+    ///
+    /// Packets which arrive before the registration reply, for example those still addressed to a
+    /// previous client on the same port, are skipped.
+    pub fn connect_with<A: ToSocketAddrs, B: ToSocketAddrs>(
+        listen: A,
+        remote: B,
+        handler: H,
+        req: RegistrationRequest,
+        config: HandshakeConfig,
     ) -> Result<Self, ClientError> {
         // Bind the listening socket first
         let socket = UdpSocket::bind(listen)?;
         socket.connect(remote)?;
 
-        // Then transmit the registration request
+        let requests_commands = req.requests_commands();
         let mut buffer = vec![];
         req.encode(&mut buffer)?;
-        socket.send(&buffer)?;
-
-        // Set a 1s timeout and wait for the registration reply to come back, if we get something else, panic for now
-        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
-        let mut incoming = vec![0u8; UDP_MAX];
-        let size = socket.recv(&mut incoming)?;
-
-        let packet = &incoming[..size];
-
-        let connection_id: u32 = match InboundMessage::decode(&packet) {
-            Ok(InboundMessage::RegistrationResult(res)) => {
-                if res.connection_success {
-                    info!("Successfully registered with ACC Server");
-                    Ok(res.connection_id)
-                } else {
-                    Err(ClientError::RegistrationError(
-                        res.error_message.to_string(),
-                    ))
-                }
-            }
-            Ok(_) => {
-                // Without the connection ID we can't shut down later on, so we cannot continue
-                panic!("Recevied a non-registration reply as first incoming packet!");
-            }
-            Err(e) => Err(ClientError::MessageDecodeError(e)),
-        }?;
+
+        let result = handshake(&socket, &buffer, requests_commands, &config)?;
+        let connection_id = result.connection_id;
 
         // Put the socket back in blocking mode
         socket.set_read_timeout(None)?;
 
         Ok(Self {
             connection_id,
-            socket,
+            read_only: result.read_only,
+            transport: Transport::Socket(socket),
+            recorder: None,
             context: Context::new(),
             stopped: false,
             handler,
+            registration: buffer,
+            handshake: config,
+            requests_commands,
+            liveness_timeout: None,
+            last_activity: Instant::now(),
+            connected: true,
+            decode_mode: DecodeMode::Lenient,
         })
     }
 
@@ -140,15 +303,168 @@ where
         // 64 bytes accommodates almost every outbound message type
         let mut buffer = Vec::with_capacity(64);
         message.encode(&mut buffer)?;
-        self.socket.send(&buffer)?;
+        match &self.transport {
+            Transport::Socket(socket) => {
+                socket.send(&buffer)?;
+            }
+            Transport::Replay(_) => trace!("Discarding outbound message during replay"),
+        }
 
         Ok(())
     }
 
+    /// Creates a client which plays back a recorded session instead of talking to the simulator.
+    ///
+    /// Messages are decoded and dispatched exactly as they would be live, but anything the client
+    /// or handler sends, including commands, is discarded. Once the capture is exhausted
+    /// [`poll`](Self::poll) returns [`ClientError::EndOfReplay`].
+    pub fn replay<R: Read + Send + 'static>(player: Player<R>, handler: H) -> Self {
+        Self {
+            connection_id: 0,
+            read_only: false,
+            transport: Transport::Replay(player.into_boxed()),
+            recorder: None,
+            context: Context::new(),
+            stopped: false,
+            handler,
+            registration: vec![],
+            handshake: HandshakeConfig::default(),
+            requests_commands: false,
+            liveness_timeout: None,
+            last_activity: Instant::now(),
+            connected: true,
+            decode_mode: DecodeMode::Lenient,
+        }
+    }
+
+    /// Changes the playback speed of a client created with [`replay`](Self::replay), live clients
+    /// are unaffected.
+    ///
+    /// Fails with [`ClientError::SocketError`] if `speed` has an invalid factor.
+    pub fn set_playback_speed(&mut self, speed: PlaybackSpeed) -> Result<(), ClientError> {
+        if let Transport::Replay(player) = &mut self.transport {
+            player.set_speed(speed)?;
+        }
+        Ok(())
+    }
+
+    /// Records every datagram subsequently received to `recorder`.
+    pub fn record<W: Write + Send + 'static>(&mut self, recorder: Recorder<W>) {
+        self.recorder = Some(recorder.into_boxed());
+    }
+
+    /// Stops recording, flushing any buffered datagrams.
+    pub fn stop_recording(&mut self) -> Result<(), std::io::Error> {
+        match self.recorder.take() {
+            Some(mut recorder) => recorder.flush(),
+            None => Ok(()),
+        }
+    }
+
     pub fn ctx(&self) -> &Context {
         &self.context
     }
 
+    /// The connection ID assigned by the simulator during registration.
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    /// `true` if the simulator did not accept the command password, in which case the command
+    /// methods will return [`ClientError::ReadOnly`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// `false` while the client is waiting for the simulator to accept a new registration.
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Sets how unrecognised enum values in incoming messages are handled.
+    ///
+    /// Clients decode leniently by default, so entries using car models or nationalities added
+    /// by newer simulator releases are still tracked. [`DecodeMode::Strict`] reports them as
+    /// [`ClientError::MessageDecodeError`] instead.
+    pub fn set_decode_mode(&mut self, mode: DecodeMode) {
+        self.decode_mode = mode;
+    }
+
+    /// Sets how long the client will wait for a [`RealtimeUpdate`] before assuming the simulator
+    /// has gone away and registering again.
+    ///
+    /// With a timeout set, [`poll`](Self::poll) returns once the timeout elapses instead of
+    /// blocking indefinitely. `None`, the default, disables the check.
+    pub fn set_liveness_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ClientError> {
+        if let (Transport::Socket(socket), None) = (&self.transport, timeout) {
+            socket.set_read_timeout(None)?;
+        }
+        self.liveness_timeout = timeout;
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Asks the simulator to resend the entry list, which will arrive as an [`EntrylistUpdate`]
+    /// followed by an [`EntrylistCar`] for each car.
+    pub fn request_entry_list(&self) -> Result<(), ClientError> {
+        Ok(self.send(EntrylistRequest::new(self.connection_id))?)
+    }
+
+    /// Asks the simulator to resend the [`TrackData`] for the current track.
+    pub fn request_track_data(&self) -> Result<(), ClientError> {
+        Ok(self.send(TrackDataRequest::new(self.connection_id))?)
+    }
+
+    /// Moves the simulator's focus to the car with the given ID.
+    pub fn focus_car(&self, car_id: u16) -> Result<(), ClientError> {
+        self.check_writable()?;
+        Ok(self.send(FocusRequest::new(self.connection_id, Some(car_id), None))?)
+    }
+
+    /// Switches the active camera, validated against the cached [`TrackData`].
+    pub fn set_camera(&self, camera_set: &str, camera: &str) -> Result<(), ClientError> {
+        self.check_writable()?;
+        validate_camera(&self.context, camera_set, camera)?;
+        Ok(self.send(FocusRequest::new(
+            self.connection_id,
+            None,
+            Some((camera_set, camera)),
+        ))?)
+    }
+
+    /// Switches the visible HUD page, validated against the cached [`TrackData`].
+    pub fn set_hud_page(&self, hud_page: &str) -> Result<(), ClientError> {
+        self.check_writable()?;
+        validate_hud_page(&self.context, hud_page)?;
+        Ok(self.send(HudPageRequest::new(self.connection_id, hud_page))?)
+    }
+
+    /// Starts an instant replay `duration_ms` long from `start_time` (in session milliseconds),
+    /// optionally focused on a specific car.
+    pub fn instant_replay(
+        &self,
+        start_time: f32,
+        duration_ms: f32,
+        car_id: Option<u16>,
+    ) -> Result<(), ClientError> {
+        self.check_writable()?;
+        Ok(self.send(InstantReplayRequest::new(
+            self.connection_id,
+            start_time,
+            duration_ms,
+            car_id,
+            None,
+        ))?)
+    }
+
+    fn check_writable(&self) -> Result<(), ClientError> {
+        if self.read_only {
+            Err(ClientError::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Sends an [`UnregisterRequest`] to the simulator and destroys the client.
     pub fn shutdown(mut self) -> Result<(), std::io::Error> {
         self.shutdown_impl()
@@ -166,9 +482,16 @@ where
 
     pub fn poll(&mut self) -> Result<(), ClientError> {
         let mut buffer = vec![0u8; UDP_MAX];
-        let size = self.socket.recv(&mut buffer)?;
-        let decoded =
-            InboundMessage::decode(&buffer[..size]).map_err(ClientError::MessageDecodeError)?;
+        let size = match self.recv_within_liveness(&mut buffer)? {
+            Some(size) => size,
+            None => return self.handle_silence(),
+        };
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&buffer[..size])?;
+        }
+        let decoded = InboundMessage::decode_with(&buffer[..size], self.decode_mode)
+            .map_err(ClientError::MessageDecodeError)?;
+        let events = self.context.process_message(&decoded);
 
         match decoded {
             InboundMessage::RealtimeUpdate(rt) => {
@@ -176,46 +499,721 @@ where
                     "Received realtime session update for time {}",
                     rt.session_time
                 );
-                self.handler.realtime_update(&self, &rt)
+                self.last_activity = Instant::now();
+                self.handler.realtime_update(self, &rt)
             }
             InboundMessage::RealtimeCarUpdate(rt) => {
                 trace!("Received realtime car update for car ID {}", rt.id);
-                self.context.update_car_state(rt.clone());
-                self.handler.realtime_car_update(&self, &rt)
+                self.handler.realtime_car_update(self, &rt)
             }
             InboundMessage::EntrylistUpdate(list) => {
                 debug!(
                     "Received entry list update with {} cars",
                     list.car_ids.len()
                 );
-                self.context.seed_entrylist(&list);
-                self.handler.entrylist_update(&self, &list)
+                self.handler.entrylist_update(self, &list)
             }
             InboundMessage::EntrylistCar(car) => {
                 debug!("Received entry information packet for car ID {}", car.id);
-                self.context.update_car_entry(car.clone());
-                self.handler.entrylist_car(&self, &car)
+                self.handler.entrylist_car(self, &car)
             }
             InboundMessage::TrackData(track) => {
                 debug!("Received track data packet for {}", track.name);
-                self.context.update_track_data(track.clone());
-                self.handler.track_data(&self, &track)
+                self.handler.track_data(self, &track)
             }
             InboundMessage::BroadcastingEvent(event) => {
                 debug!("Received broadcasting event {:?}", event.event_type);
-                self.handler.broadcasting_event(&self, &event)
+                self.handler.broadcasting_event(self, &event)
+            }
+            InboundMessage::RegistrationResult(_) => {
+                // A registration result while connected is a stray reply, only act on it if
+                // we're waiting to reconnect
+                if !self.connected {
+                    let reply =
+                        handshake_reply(&buffer[..size], self.requests_commands, &self.handshake)?;
+                    match reply {
+                        HandshakeReply::Skip => {}
+                        HandshakeReply::Registered(res) => {
+                            info!("Re-registered with ACC Server");
+                            self.connection_id = res.connection_id;
+                            self.read_only = res.read_only;
+                            self.connected = true;
+                            self.last_activity = Instant::now();
+                            self.request_entry_list()?;
+                            self.request_track_data()?;
+                            self.handler.reconnected(self)
+                        }
+                        HandshakeReply::Failed { error, unregister } => {
+                            if let (Some(unregister), Transport::Socket(socket)) =
+                                (unregister, &self.transport)
+                            {
+                                socket.send(&unregister)?;
+                            }
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+        }
+
+        for event in &events {
+            self.dispatch_event(event);
+        }
+        Ok(())
+    }
+
+    fn dispatch_event(&self, event: &SessionEvent) {
+        trace!("Derived session event {:?}", event);
+        match event {
+            SessionEvent::PitEntry { car_id, lap } => self.handler.pit_entry(self, *car_id, *lap),
+            SessionEvent::PitExit { car_id, stop } => self.handler.pit_exit(self, *car_id, stop),
+            SessionEvent::StintFinished { car_id, stint } => {
+                self.handler.stint_finished(self, *car_id, stint)
+            }
+            SessionEvent::PositionChanged { car_id, from, to } => {
+                self.handler.position_changed(self, *car_id, *from, *to)
+            }
+            SessionEvent::Overtake {
+                car_id,
+                overtaken_car_id,
+            } => self.handler.overtake(self, *car_id, *overtaken_car_id),
+            SessionEvent::CarStopped { car_id } => self.handler.car_stopped(self, *car_id),
+            SessionEvent::DriverSwap { car_id, from, to } => {
+                self.handler.driver_swap(self, *car_id, *from, *to)
+            }
+            SessionEvent::SessionPhaseChanged { from, to } => {
+                self.handler.session_phase_changed(self, *from, *to)
+            }
+            SessionEvent::WeatherChanged { from, to } => {
+                self.handler.weather_changed(self, *from, *to)
+            }
+            SessionEvent::SessionArchived { index } => {
+                if let Some(session) = self.context.archived_sessions().get(*index) {
+                    self.handler.session_archived(self, session)
+                }
+            }
+            SessionEvent::SectorCompleted {
+                car_id,
+                sector,
+                time,
+                rating,
+            } => self
+                .handler
+                .sector_completed(self, *car_id, *sector, *time, *rating),
+        }
+    }
+
+    // Returns `None` if the liveness timeout elapses before a packet arrives
+    fn recv_within_liveness(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, ClientError> {
+        let socket = match &mut self.transport {
+            Transport::Socket(socket) => socket,
+            // Recorded sessions are never considered silent
+            Transport::Replay(player) => {
+                let datagram = player.next_datagram()?.ok_or(ClientError::EndOfReplay)?;
+                let size = datagram.payload.len();
+                buffer[..size].copy_from_slice(&datagram.payload);
+                return Ok(Some(size));
+            }
+        };
+
+        let timeout = match self.liveness_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(Some(socket.recv(buffer)?)),
+        };
+
+        let remaining = match timeout.checked_sub(self.last_activity.elapsed()) {
+            Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+            _ => return Ok(None),
+        };
+        socket.set_read_timeout(Some(remaining))?;
+
+        match socket.recv(buffer) {
+            Ok(size) => Ok(Some(size)),
+            Err(e) if is_timeout(&e) => Ok(None),
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                // The simulator isn't listening right now, and says so as soon as we send
+                // anything, so wait out the timeout as if it had stayed silent
+                std::thread::sleep(remaining);
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn handle_silence(&mut self) -> Result<(), ClientError> {
+        if self.connected {
+            warn!("No realtime update received within liveness timeout, registering again");
+            self.connected = false;
+            self.handler.disconnected(self);
+        }
+        // Restart the timer so we only retry registration once per timeout period
+        self.last_activity = Instant::now();
+        if let Transport::Socket(socket) = &self.transport {
+            socket.send(&self.registration)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_registration(
+    result: RegistrationResult<'static>,
+    requests_commands: bool,
+    config: &HandshakeConfig,
+) -> Result<RegistrationResult<'static>, ClientError> {
+    if !result.connection_success {
+        Err(ClientError::RegistrationError(
+            result.error_message.into_owned(),
+        ))
+    } else if result.read_only && requests_commands && !config.allow_read_only {
+        Err(ClientError::ReadOnlyGranted)
+    } else {
+        info!("Successfully registered with ACC Server");
+        Ok(result)
+    }
+}
+
+/// What a client should do with a packet received while waiting for its registration reply.
+pub(crate) enum HandshakeReply {
+    /// The packet isn't the reply, keep waiting.
+    Skip,
+    Registered(RegistrationResult<'static>),
+    /// Registration failed. If the simulator did register us, `unregister` must be sent to
+    /// release the connection before returning the error.
+    Failed {
+        error: ClientError,
+        unregister: Option<Vec<u8>>,
+    },
+}
+
+/// Decides how a packet received during the handshake is handled, leaving the I/O to the client.
+pub(crate) fn handshake_reply(
+    packet: &[u8],
+    requests_commands: bool,
+    config: &HandshakeConfig,
+) -> Result<HandshakeReply, ClientError> {
+    let res = match InboundMessage::decode(packet) {
+        Ok(InboundMessage::RegistrationResult(res)) => res.into_owned(),
+        Ok(_) => {
+            trace!("Skipping unrelated packet during handshake");
+            return Ok(HandshakeReply::Skip);
+        }
+        Err(_) => {
+            trace!("Skipping undecodable packet during handshake");
+            return Ok(HandshakeReply::Skip);
+        }
+    };
+
+    let connection_id = res.connection_id;
+    match check_registration(res, requests_commands, config) {
+        Ok(result) => Ok(HandshakeReply::Registered(result)),
+        Err(ClientError::ReadOnlyGranted) => {
+            // We were registered, so the connection has to be released before bailing out
+            let mut unregister = vec![];
+            UnregisterRequest::new(connection_id).encode(&mut unregister)?;
+            Ok(HandshakeReply::Failed {
+                error: ClientError::ReadOnlyGranted,
+                unregister: Some(unregister),
+            })
+        }
+        Err(error) => Ok(HandshakeReply::Failed {
+            error,
+            unregister: None,
+        }),
+    }
+}
+
+// Platforms disagree on which error a read timeout produces
+pub(crate) fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+fn handshake(
+    socket: &UdpSocket,
+    registration: &[u8],
+    requests_commands: bool,
+    config: &HandshakeConfig,
+) -> Result<RegistrationResult<'static>, ClientError> {
+    let mut incoming = vec![0u8; UDP_MAX];
+
+    for attempt in 1..=config.attempts {
+        debug!("Sending registration request, attempt {}", attempt);
+        socket.send(registration)?;
+
+        let deadline = Instant::now() + config.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+
+            let size = match socket.recv(&mut incoming) {
+                Ok(size) => size,
+                Err(e) if is_timeout(&e) => break,
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    // Nothing is listening yet, wait out the rest of this attempt
+                    std::thread::sleep(remaining);
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            match handshake_reply(&incoming[..size], requests_commands, config)? {
+                HandshakeReply::Skip => {}
+                HandshakeReply::Registered(result) => return Ok(result),
+                HandshakeReply::Failed { error, unregister } => {
+                    if let Some(unregister) = unregister {
+                        socket.send(&unregister)?;
+                    }
+                    return Err(error);
+                }
             }
-            InboundMessage::RegistrationResult(_) => (),
         }
+    }
+
+    Err(ClientError::RegistrationTimeout(config.attempts))
+}
+
+pub(crate) fn validate_hud_page(context: &Context, hud_page: &str) -> Result<(), ClientError> {
+    let track = context.track_data().ok_or(ClientError::MissingTrackData)?;
+    if track.hud_pages.iter().any(|h| h == hud_page) {
         Ok(())
+    } else {
+        Err(ClientError::UnknownHudPage(hud_page.to_string()))
+    }
+}
+
+pub(crate) fn validate_camera(
+    context: &Context,
+    camera_set: &str,
+    camera: &str,
+) -> Result<(), ClientError> {
+    let track = context.track_data().ok_or(ClientError::MissingTrackData)?;
+    match track.camera_sets.get(camera_set) {
+        Some(cameras) if cameras.iter().any(|c| c == camera) => Ok(()),
+        _ => Err(ClientError::UnknownCamera(
+            camera_set.to_string(),
+            camera.to_string(),
+        )),
     }
 }
 
 impl<H: MessageHandler> Drop for BroadcastingClient<H> {
     fn drop(&mut self) {
         if !self.stopped {
-            self.shutdown_impl()
-                .expect("Failed to send shutdown on Drop");
+            if let Err(e) = self.shutdown_impl() {
+                warn!("Failed to unregister on drop: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::thread;
+
+    #[derive(Default)]
+    struct TransitionCounter {
+        disconnects: Cell<u32>,
+        reconnects: Cell<u32>,
+    }
+
+    impl MessageHandler for TransitionCounter {
+        fn disconnected<H: MessageHandler>(&self, _client: &BroadcastingClient<H>) {
+            self.disconnects.set(self.disconnects.get() + 1);
         }
+
+        fn reconnected<H: MessageHandler>(&self, _client: &BroadcastingClient<H>) {
+            self.reconnects.set(self.reconnects.get() + 1);
+        }
+    }
+
+    fn registration_result(connection_id: u8) -> [u8; 9] {
+        [
+            0x01,
+            connection_id,
+            0x00,
+            0x00,
+            0x00,
+            0x01,
+            0x00,
+            0x00,
+            0x00,
+        ]
+    }
+
+    #[test]
+    fn reconnects_after_liveness_timeout() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server.send(&registration_result(7)).unwrap();
+
+            // Stay silent until the client registers again
+            let size = server.recv(&mut buf).unwrap();
+            assert_eq!(buf[0], 0x01);
+            assert!(size > 1);
+            server.send(&registration_result(8)).unwrap();
+
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let size = server.recv(&mut buf).unwrap();
+                requests.push(buf[..size].to_vec());
+            }
+            requests
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "");
+        let mut client = BroadcastingClient::connect(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+        )
+        .unwrap();
+        client
+            .set_liveness_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        assert_eq!(client.connection_id(), 7);
+
+        client.poll().unwrap();
+        assert!(!client.is_connected());
+        assert_eq!(client.handler.disconnects.get(), 1);
+
+        client.poll().unwrap();
+        assert!(client.is_connected());
+        assert_eq!(client.connection_id(), 8);
+        assert_eq!(client.handler.reconnects.get(), 1);
+
+        let requests = stand_in.join().unwrap();
+        assert_eq!(requests[0], &[0x0a, 0x08, 0x00, 0x00, 0x00]);
+        assert_eq!(requests[1], &[0x0b, 0x08, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn registers_once_per_timeout_while_simulator_is_down() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server.send(&registration_result(7)).unwrap();
+            // Closing the socket leaves nothing listening on the port, so the client's
+            // registrations are refused
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "");
+        let mut client = BroadcastingClient::connect(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+        )
+        .unwrap();
+        stand_in.join().unwrap();
+
+        let timeout = Duration::from_millis(50);
+        client.set_liveness_timeout(Some(timeout)).unwrap();
+
+        // Each poll that hears nothing sends one registration
+        let started = Instant::now();
+        let mut registrations = 0;
+        while started.elapsed() < timeout * 6 {
+            client.poll().unwrap();
+            registrations += 1;
+        }
+        assert!(registrations <= 7, "{} registrations sent", registrations);
+        assert!(!client.is_connected());
+        assert_eq!(client.handler.disconnects.get(), 1);
+    }
+
+    #[test]
+    fn handshake_skips_unrelated_packets() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server
+                .send(&[0x04, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03])
+                .unwrap();
+            server.send(b"garbage").unwrap();
+            server.send(&registration_result(7)).unwrap();
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "");
+        let client = BroadcastingClient::connect(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+        )
+        .unwrap();
+        stand_in.join().unwrap();
+
+        assert_eq!(client.connection_id(), 7);
+    }
+
+    #[test]
+    fn handshake_reply_decisions() {
+        let config = HandshakeConfig::default();
+        let read_only = [0x01, 0x07, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00];
+
+        assert!(matches!(
+            handshake_reply(b"garbage", true, &config),
+            Ok(HandshakeReply::Skip)
+        ));
+        assert!(matches!(
+            handshake_reply(&registration_result(7), true, &config),
+            Ok(HandshakeReply::Registered(res)) if res.connection_id == 7
+        ));
+        match handshake_reply(&read_only, true, &config) {
+            Ok(HandshakeReply::Failed {
+                error: ClientError::ReadOnlyGranted,
+                unregister: Some(unregister),
+            }) => assert_eq!(unregister, &[0x09, 0x07, 0x00, 0x00, 0x00]),
+            _ => panic!("Expected the read-only registration to be released"),
+        }
+        // Read-only access is fine when no commands were requested
+        assert!(matches!(
+            handshake_reply(&read_only, false, &config),
+            Ok(HandshakeReply::Registered(_))
+        ));
+    }
+
+    #[test]
+    fn handshake_times_out_after_retries() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "");
+        let config = HandshakeConfig {
+            timeout: Duration::from_millis(20),
+            attempts: 2,
+            ..HandshakeConfig::default()
+        };
+        let res = BroadcastingClient::connect_with(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+            config,
+        );
+        assert!(matches!(res, Err(ClientError::RegistrationTimeout(2))));
+
+        // Both attempts should have reached the server
+        let mut buf = [0u8; 64];
+        server.set_nonblocking(true).unwrap();
+        assert_eq!(server.recv(&mut buf).unwrap(), 19);
+        assert_eq!(server.recv(&mut buf).unwrap(), 19);
+    }
+
+    #[test]
+    fn handshake_rejects_read_only_when_commands_requested() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server
+                .send(&[0x01, 0x07, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00])
+                .unwrap();
+
+            // The client should release the registration it was given
+            let size = server.recv(&mut buf).unwrap();
+            buf[..size].to_vec()
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "wrong");
+        let res = BroadcastingClient::connect(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+        );
+        assert!(matches!(res, Err(ClientError::ReadOnlyGranted)));
+        assert_eq!(stand_in.join().unwrap(), &[0x09, 0x07, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn reconnect_rejects_read_only_when_commands_requested() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server.send(&registration_result(7)).unwrap();
+
+            // Grant read-only access when the client registers again
+            server.recv(&mut buf).unwrap();
+            server
+                .send(&[0x01, 0x08, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00])
+                .unwrap();
+
+            let size = server.recv(&mut buf).unwrap();
+            (server, buf[..size].to_vec())
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "wrong");
+        let mut client = BroadcastingClient::connect(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+        )
+        .unwrap();
+        client
+            .set_liveness_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+
+        client.poll().unwrap();
+        assert!(!client.is_connected());
+
+        assert!(matches!(client.poll(), Err(ClientError::ReadOnlyGranted)));
+        assert!(!client.is_connected());
+        assert!(!client.is_read_only());
+        assert_eq!(client.handler.reconnects.get(), 0);
+
+        // The read-only registration is released
+        let (_server, unregister) = stand_in.join().unwrap();
+        assert_eq!(unregister, &[0x09, 0x08, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn read_only_client_refuses_commands() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let stand_in = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (_, peer) = server.recv_from(&mut buf).unwrap();
+            server.connect(peer).unwrap();
+            server
+                .send(&[0x01, 0x07, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00])
+                .unwrap();
+            server
+        });
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "wrong");
+        let config = HandshakeConfig {
+            allow_read_only: true,
+            ..HandshakeConfig::default()
+        };
+        let client = BroadcastingClient::connect_with(
+            "127.0.0.1:0",
+            server_addr,
+            TransitionCounter::default(),
+            req,
+            config,
+        )
+        .unwrap();
+        // Keep the stand-in open so the client can still send to it
+        let _server = stand_in.join().unwrap();
+
+        assert!(client.is_read_only());
+        assert!(matches!(client.focus_car(1001), Err(ClientError::ReadOnly)));
+        assert!(matches!(
+            client.instant_replay(0.0, 1000.0, None),
+            Err(ClientError::ReadOnly)
+        ));
+        assert!(client.request_entry_list().is_ok());
+    }
+
+    fn replay_client() -> BroadcastingClient<TransitionCounter> {
+        let capture = Recorder::new(vec![]).unwrap().into_inner().unwrap();
+        let player = Player::new(std::io::Cursor::new(capture), PlaybackSpeed::Stepwise).unwrap();
+        BroadcastingClient::replay(player, TransitionCounter::default())
+    }
+
+    fn track_data() -> TrackData<'static> {
+        let mut camera_sets = std::collections::HashMap::new();
+        camera_sets.insert("Drivable".into(), vec!["Cockpit".into(), "Chase".into()]);
+        camera_sets.insert("set1".into(), vec!["CameraTV1".into()]);
+        TrackData {
+            connection_id: 0,
+            name: "Circuit Zolder".into(),
+            id: 19,
+            distance: 4011,
+            camera_sets,
+            hud_pages: vec!["Basic HUD".into(), "Broadcasting".into()],
+        }
+    }
+
+    #[test]
+    fn camera_validated_against_track_data() {
+        let mut client = replay_client();
+        assert!(matches!(
+            client.set_camera("Drivable", "Cockpit"),
+            Err(ClientError::MissingTrackData)
+        ));
+
+        client.context.update_track_data(track_data());
+        assert!(client.set_camera("Drivable", "Cockpit").is_ok());
+        assert!(client.set_camera("set1", "CameraTV1").is_ok());
+        match client.set_camera("Drivable", "CameraTV1") {
+            Err(ClientError::UnknownCamera(set, camera)) => {
+                assert_eq!(set, "Drivable");
+                assert_eq!(camera, "CameraTV1");
+            }
+            other => panic!("Expected an unknown camera, got {:?}", other),
+        }
+        assert!(matches!(
+            client.set_camera("Helicam", "Cockpit"),
+            Err(ClientError::UnknownCamera(_, _))
+        ));
+    }
+
+    #[test]
+    fn hud_page_validated_against_track_data() {
+        let mut client = replay_client();
+        assert!(matches!(
+            client.set_hud_page("Broadcasting"),
+            Err(ClientError::MissingTrackData)
+        ));
+
+        client.context.update_track_data(track_data());
+        assert!(client.set_hud_page("Broadcasting").is_ok());
+        match client.set_hud_page("broadcasting") {
+            Err(ClientError::UnknownHudPage(page)) => assert_eq!(page, "broadcasting"),
+            other => panic!("Expected an unknown HUD page, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replays_recorded_session() {
+        let mut recorder = Recorder::new(vec![]).unwrap();
+        recorder
+            .record(&[0x04, 0x07, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03])
+            .unwrap();
+        recorder
+            .record(include_bytes!("../docs/pcap/track_data.bin"))
+            .unwrap();
+        let capture = recorder.into_inner().unwrap();
+
+        let player = Player::new(std::io::Cursor::new(capture), PlaybackSpeed::Stepwise).unwrap();
+        let mut client = BroadcastingClient::replay(player, TransitionCounter::default());
+
+        client.poll().unwrap();
+        assert!(client.ctx().track_data().is_none());
+        client.poll().unwrap();
+        assert_eq!(client.ctx().track_data().unwrap().name, "Circuit Zolder");
+
+        // Commands are accepted but go nowhere
+        assert!(client.focus_car(1001).is_ok());
+        assert!(matches!(client.poll(), Err(ClientError::EndOfReplay)));
     }
 }
@@ -1,6 +1,15 @@
+#[cfg(feature = "tokio")]
+pub mod async_client;
 pub mod client;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod pcap;
 pub mod protocol;
+pub mod replay;
+#[cfg(feature = "serde")]
+pub mod results;
 pub mod session;
+pub mod track;
 
 #[cfg(test)]
 mod tests {
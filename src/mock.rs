@@ -0,0 +1,609 @@
+//! A stand-in for the simulator's broadcasting service, for testing clients without ACC.
+//!
+//! [`MockServer`] binds a UDP socket, answers registration requests, and plays a [`Script`] of
+//! datagrams to each registered client at the update interval it asked for. Every other packet
+//! received from a registered client is appended to a [`CommandLog`], so tests can assert on what
+//! a client sent.
+//!
+//! This module is only available with the `mock` feature enabled, which also builds the
+//! `acbc-mock` binary.
+//!
+//! # Example
+//!
+//! ```
+//! use acbc::client::{BroadcastingClient, MessageHandler};
+//! use acbc::mock::{MockServer, MockServerConfig, Script};
+//! use acbc::protocol::RegistrationRequest;
+//!
+//! struct Handler;
+//! impl MessageHandler for Handler {}
+//!
+//! let server = MockServer::bind("127.0.0.1:0", MockServerConfig::default(), Script::new())
+//!     .unwrap()
+//!     .spawn();
+//!
+//! let req = RegistrationRequest::new("Director", "asd", 250, "");
+//! let client = BroadcastingClient::connect("127.0.0.1:0", server.local_addr(), Handler, req).unwrap();
+//! client.request_entry_list().unwrap();
+//! # client.shutdown().unwrap();
+//! ```
+
+use crate::protocol::{
+    ClientMessage, InboundMessage, RegistrationRequest, RegistrationResult, PROTOCOL_VERSION,
+};
+use log::{debug, info, warn};
+use nom_supreme::error::ErrorTree;
+use nom_supreme::final_parser::ByteOffset;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::client::{is_timeout, UDP_MAX};
+
+// How often a spawned server checks whether it has been asked to stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Passwords the mock server will accept.
+#[derive(Debug, Clone)]
+pub struct MockServerConfig {
+    /// Clients registering with any other password are rejected.
+    pub password: String,
+    /// Clients registering with this password are granted command access, everyone else is
+    /// registered read-only. An empty password never grants command access.
+    pub command_password: String,
+}
+
+impl Default for MockServerConfig {
+    fn default() -> Self {
+        Self {
+            password: "asd".to_string(),
+            command_password: String::new(),
+        }
+    }
+}
+
+/// The datagrams played to each registered client.
+///
+/// The entry list and track data are sent after registration and whenever a client requests
+/// them. Frames are sent in order, one per update interval, and the server falls silent once they
+/// run out.
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    entry_list: Vec<Vec<u8>>,
+    track_data: Option<Vec<u8>>,
+    frames: Vec<Vec<Vec<u8>>>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Script::default()
+    }
+
+    /// Sets the entry list, an `EntrylistUpdate` datagram followed by one `EntrylistCar` datagram
+    /// per car.
+    pub fn set_entry_list(&mut self, update: Vec<u8>, cars: Vec<Vec<u8>>) {
+        self.entry_list = Some(update).into_iter().chain(cars).collect();
+    }
+
+    /// Sets the `TrackData` datagram.
+    pub fn set_track_data(&mut self, track_data: Vec<u8>) {
+        self.track_data = Some(track_data);
+    }
+
+    /// Appends a frame, typically a `RealtimeUpdate` followed by a `RealtimeCarUpdate` per car and
+    /// any `BroadcastingEvent`s.
+    pub fn push_frame(&mut self, datagrams: Vec<Vec<u8>>) {
+        self.frames.push(datagrams);
+    }
+
+    /// Builds a script from datagrams the simulator sent, for example those read from a
+    /// [`Player`](crate::replay::Player) or [`CaptureReader`](crate::pcap::CaptureReader).
+    ///
+    /// Each `RealtimeUpdate` starts a new frame, and the most recent entry list and track data are
+    /// kept aside to be sent on request.
+    pub fn from_datagrams<I: IntoIterator<Item = Vec<u8>>>(datagrams: I) -> Self {
+        let mut script = Script::new();
+        let mut entry_list_complete = false;
+
+        for datagram in datagrams {
+            match datagram.first() {
+                Some(0x02) => script.frames.push(vec![datagram]),
+                Some(0x03) | Some(0x07) => match script.frames.last_mut() {
+                    Some(frame) => frame.push(datagram),
+                    None => script.frames.push(vec![datagram]),
+                },
+                Some(0x04) => {
+                    script.entry_list = vec![datagram];
+                    entry_list_complete = false;
+                }
+                Some(0x06) if !entry_list_complete => script.entry_list.push(datagram),
+                Some(0x05) => {
+                    script.track_data = Some(datagram);
+                    entry_list_complete = true;
+                }
+                _ => (),
+            }
+        }
+
+        script
+    }
+}
+
+/// A command received by the mock server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedCommand {
+    pub from: SocketAddr,
+    pub connection_id: u32,
+    /// The raw datagram, including the packet type.
+    pub payload: Vec<u8>,
+}
+
+impl ReceivedCommand {
+    /// Decodes the command from its payload.
+    pub fn message(&self) -> Result<ClientMessage<'_>, ErrorTree<ByteOffset>> {
+        ClientMessage::decode(&self.payload)
+    }
+}
+
+/// A shared, append-only record of the commands received by a [`MockServer`].
+#[derive(Debug, Clone, Default)]
+pub struct CommandLog(Arc<Mutex<Vec<ReceivedCommand>>>);
+
+impl CommandLog {
+    /// A copy of every command received so far.
+    pub fn snapshot(&self) -> Vec<ReceivedCommand> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Removes and returns every command received so far.
+    pub fn drain(&self) -> Vec<ReceivedCommand> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    fn push(&self, command: ReceivedCommand) {
+        self.0.lock().unwrap().push(command);
+    }
+}
+
+#[derive(Debug)]
+struct RegisteredClient {
+    connection_id: u32,
+    interval: Duration,
+    next_frame: usize,
+    due: Instant,
+}
+
+pub struct MockServer {
+    socket: UdpSocket,
+    config: MockServerConfig,
+    script: Script,
+    clients: HashMap<SocketAddr, RegisteredClient>,
+    next_connection_id: u32,
+    commands: CommandLog,
+}
+
+impl MockServer {
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        config: MockServerConfig,
+        script: Script,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            config,
+            script,
+            clients: HashMap::new(),
+            next_connection_id: 1,
+            commands: CommandLog::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// A handle to the log of commands received from clients.
+    pub fn commands(&self) -> CommandLog {
+        self.commands.clone()
+    }
+
+    /// Handles at most one incoming packet, waiting no longer than `timeout`, then sends any
+    /// frames which have fallen due.
+    pub fn run_once(&mut self, timeout: Duration) -> std::io::Result<()> {
+        let now = Instant::now();
+        let wait = self
+            .clients
+            .values()
+            .filter(|c| c.next_frame < self.script.frames.len())
+            .map(|c| c.due.saturating_duration_since(now))
+            .fold(timeout, Duration::min)
+            // A zero read timeout is an error, so always wait at least a little
+            .max(Duration::from_millis(1));
+        self.socket.set_read_timeout(Some(wait))?;
+
+        let mut buffer = vec![0u8; UDP_MAX];
+        match self.socket.recv_from(&mut buffer) {
+            Ok((size, from)) => {
+                if let Err(e) = self.handle_packet(&buffer[..size], from) {
+                    self.drop_client(from, &e);
+                }
+            }
+            Err(e) if is_timeout(&e) => (),
+            // Windows reports a client which went away without unregistering on the next receive,
+            // without saying which one, so the error only concerns that client
+            Err(e) if is_unreachable(&e) => warn!("Client unreachable: {}", e),
+            Err(e) => return Err(e),
+        }
+
+        self.send_due_frames();
+        Ok(())
+    }
+
+    /// Serves clients until an I/O error occurs on the server's socket. Clients which can't be
+    /// reached are dropped without affecting the others.
+    pub fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            self.run_once(Duration::from_secs(1))?;
+        }
+    }
+
+    /// Serves clients on a background thread until the returned handle is stopped or dropped.
+    pub fn spawn(mut self) -> MockServerHandle {
+        let addr = self
+            .local_addr()
+            .expect("Mock server socket has no local address");
+        let commands = self.commands();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                self.run_once(STOP_CHECK_INTERVAL)?;
+            }
+            Ok(())
+        });
+
+        MockServerHandle {
+            addr,
+            commands,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &[u8], from: SocketAddr) -> std::io::Result<()> {
+        let message = match ClientMessage::decode(packet) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Ignoring malformed packet from {}: {:?}", from, e);
+                return Ok(());
+            }
+        };
+
+        match message {
+            ClientMessage::RegistrationRequest(request) => self.register(&request, from),
+            ClientMessage::UnregisterRequest(_) => {
+                if let Some(client) = self.clients.remove(&from) {
+                    info!("Client {} unregistered", client.connection_id);
+                }
+                Ok(())
+            }
+            message => {
+                let connection_id = match self.clients.get(&from) {
+                    Some(client) => client.connection_id,
+                    None => {
+                        debug!("Ignoring packet from unregistered client {}", from);
+                        return Ok(());
+                    }
+                };
+                self.commands.push(ReceivedCommand {
+                    from,
+                    connection_id,
+                    payload: packet.to_vec(),
+                });
+
+                match message {
+                    ClientMessage::EntrylistRequest(_) => self.send_entry_list(from, connection_id),
+                    ClientMessage::TrackDataRequest(_) => self.send_track_data(from, connection_id),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn register(&mut self, request: &RegistrationRequest, from: SocketAddr) -> std::io::Result<()> {
+        let rejection = if request.version() != PROTOCOL_VERSION {
+            Some("Protocol version mismatch")
+        } else if request.password() != self.config.password {
+            Some("Password incorrect")
+        } else {
+            None
+        };
+
+        let connection_id = self.next_connection_id;
+        self.next_connection_id += 1;
+        let read_only = self.config.command_password.is_empty()
+            || request.command_password() != self.config.command_password;
+
+        let mut reply = vec![];
+        InboundMessage::RegistrationResult(RegistrationResult {
+            connection_id,
+            connection_success: rejection.is_none(),
+            read_only,
+            error_message: Cow::Borrowed(rejection.unwrap_or("")),
+        })
+        .encode(&mut reply)?;
+        self.socket.send_to(&reply, from)?;
+
+        if let Some(reason) = rejection {
+            info!("Rejected registration from {}: {}", from, reason);
+            return Ok(());
+        }
+
+        info!(
+            "Registered {} as connection {} (read only: {})",
+            from, connection_id, read_only
+        );
+        let interval =
+            Duration::from_millis(request.interval() as u64).max(Duration::from_millis(1));
+        self.clients.insert(
+            from,
+            RegisteredClient {
+                connection_id,
+                interval,
+                next_frame: 0,
+                due: Instant::now() + interval,
+            },
+        );
+
+        self.send_entry_list(from, connection_id)?;
+        self.send_track_data(from, connection_id)
+    }
+
+    fn send_entry_list(&self, to: SocketAddr, connection_id: u32) -> std::io::Result<()> {
+        for datagram in &self.script.entry_list {
+            self.socket
+                .send_to(&with_connection_id(datagram, connection_id), to)?;
+        }
+        Ok(())
+    }
+
+    fn send_track_data(&self, to: SocketAddr, connection_id: u32) -> std::io::Result<()> {
+        if let Some(datagram) = &self.script.track_data {
+            self.socket
+                .send_to(&with_connection_id(datagram, connection_id), to)?;
+        }
+        Ok(())
+    }
+
+    fn send_due_frames(&mut self) {
+        let now = Instant::now();
+        let socket = &self.socket;
+        let mut failed = vec![];
+        for (addr, client) in self.clients.iter_mut() {
+            if client.due > now {
+                continue;
+            }
+            if let Some(frame) = self.script.frames.get(client.next_frame) {
+                let sent = frame
+                    .iter()
+                    .try_for_each(|datagram| socket.send_to(datagram, addr).map(|_| ()));
+                if let Err(e) = sent {
+                    failed.push((*addr, e));
+                    continue;
+                }
+                client.next_frame += 1;
+                client.due += client.interval;
+            }
+        }
+        for (addr, e) in failed {
+            self.drop_client(addr, &e);
+        }
+    }
+
+    fn drop_client(&mut self, addr: SocketAddr, error: &std::io::Error) {
+        warn!("Dropping client {} after socket error: {}", addr, error);
+        self.clients.remove(&addr);
+    }
+}
+
+/// Controls a [`MockServer`] running on a background thread.
+pub struct MockServerHandle {
+    addr: SocketAddr,
+    commands: CommandLog,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl MockServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn commands(&self) -> CommandLog {
+        self.commands.clone()
+    }
+
+    /// Stops the server, returning the error which ended it early, if any.
+    pub fn stop(mut self) -> std::io::Result<()> {
+        self.stop_impl()
+    }
+
+    fn stop_impl(&mut self) -> std::io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => thread.join().expect("Mock server thread panicked"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for MockServerHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_impl() {
+            warn!("Mock server stopped with error: {}", e);
+        }
+    }
+}
+
+fn is_unreachable(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused
+    )
+}
+
+// Entry list headers and track data carry the recipient's connection ID after the packet type
+fn with_connection_id(datagram: &[u8], connection_id: u32) -> Vec<u8> {
+    let mut datagram = datagram.to_vec();
+    if matches!(datagram.first(), Some(0x04) | Some(0x05)) && datagram.len() >= 5 {
+        datagram[1..5].copy_from_slice(&connection_id.to_le_bytes());
+    }
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{BroadcastingClient, ClientError, HandshakeConfig, MessageHandler};
+    use crate::protocol::{HudPageRequest, RegistrationRequest};
+
+    struct NoopHandler;
+    impl MessageHandler for NoopHandler {}
+
+    fn script() -> Script {
+        let mut script = Script::new();
+        script.set_entry_list(
+            vec![0x04, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03],
+            vec![],
+        );
+        script.set_track_data(include_bytes!("../docs/pcap/track_data.bin").to_vec());
+        script.push_frame(vec![
+            include_bytes!("../docs/pcap/realtime_update.bin").to_vec(),
+            include_bytes!("../docs/pcap/realtime_car_update.bin").to_vec(),
+        ]);
+        script
+    }
+
+    fn config() -> MockServerConfig {
+        MockServerConfig {
+            password: "asd".to_string(),
+            command_password: "director".to_string(),
+        }
+    }
+
+    #[test]
+    fn streams_script_and_logs_commands() {
+        let server = MockServer::bind("127.0.0.1:0", config(), script())
+            .unwrap()
+            .spawn();
+
+        let req = RegistrationRequest::new("Test", "asd", 10, "director");
+        let mut client =
+            BroadcastingClient::connect("127.0.0.1:0", server.local_addr(), NoopHandler, req)
+                .unwrap();
+        assert!(!client.is_read_only());
+
+        // Entry list, track data, then a single frame
+        for _ in 0..4 {
+            client.poll().unwrap();
+        }
+        assert_eq!(client.ctx().track_data().unwrap().name, "Circuit Zolder");
+        assert!(client.ctx().car_by_id(1001).unwrap().state.is_some());
+
+        client.set_hud_page("Broadcasting").unwrap();
+        client.shutdown().unwrap();
+        let commands = server.commands();
+        server.stop().unwrap();
+
+        let commands = commands.snapshot();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(
+            commands[0].message().unwrap(),
+            ClientMessage::HudPageRequest(HudPageRequest::new(1, "Broadcasting"))
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let server = MockServer::bind("127.0.0.1:0", config(), script())
+            .unwrap()
+            .spawn();
+
+        let req = RegistrationRequest::new("Test", "wrong", 250, "");
+        let res = BroadcastingClient::connect("127.0.0.1:0", server.local_addr(), NoopHandler, req);
+        assert!(matches!(res, Err(ClientError::RegistrationError(_))));
+    }
+
+    #[test]
+    fn grants_read_only_for_wrong_command_password() {
+        let server = MockServer::bind("127.0.0.1:0", config(), script())
+            .unwrap()
+            .spawn();
+
+        let req = RegistrationRequest::new("Test", "asd", 250, "wrong");
+        let config = HandshakeConfig {
+            allow_read_only: true,
+            ..HandshakeConfig::default()
+        };
+        let client = BroadcastingClient::connect_with(
+            "127.0.0.1:0",
+            server.local_addr(),
+            NoopHandler,
+            req,
+            config,
+        )
+        .unwrap();
+        assert!(client.is_read_only());
+    }
+
+    #[test]
+    fn drops_clients_which_cannot_be_reached() {
+        let mut server = MockServer::bind("127.0.0.1:0", config(), script()).unwrap();
+        let unreachable: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        server.clients.insert(
+            unreachable,
+            RegisteredClient {
+                connection_id: 1,
+                interval: Duration::from_millis(10),
+                next_frame: 0,
+                due: Instant::now(),
+            },
+        );
+
+        server.run_once(Duration::from_millis(1)).unwrap();
+        assert!(server.clients.is_empty());
+
+        // The server keeps serving other clients
+        let server = server.spawn();
+        let req = RegistrationRequest::new("Test", "asd", 10, "director");
+        let mut client =
+            BroadcastingClient::connect("127.0.0.1:0", server.local_addr(), NoopHandler, req)
+                .unwrap();
+        client.poll().unwrap();
+    }
+
+    #[test]
+    fn script_from_datagrams() {
+        let script = Script::from_datagrams(vec![
+            vec![0x04, 0x01],
+            vec![0x06, 0x01],
+            vec![0x05, 0x01],
+            vec![0x02, 0x01],
+            vec![0x03, 0x01],
+            vec![0x02, 0x02],
+            vec![0x07, 0x02],
+            vec![0x03, 0x02],
+        ]);
+
+        assert_eq!(script.entry_list.len(), 2);
+        assert!(script.track_data.is_some());
+        assert_eq!(script.frames.len(), 2);
+        assert_eq!(script.frames[1].len(), 3);
+    }
+}
@@ -0,0 +1,585 @@
+//! Extraction of Broadcasting API traffic from packet captures.
+//!
+//! [`CaptureReader`] reads classic pcap and pcapng files, such as those saved by Wireshark or
+//! `tcpdump`, and yields the UDP payloads sent to or from the broadcasting port. Captures may be
+//! gzip compressed if the `gzip` feature is enabled.
+//!
+//! Only unfragmented UDP over IPv4 or IPv6 is extracted, on Ethernet, loopback, Linux cooked or
+//! raw IP links. Everything else in the capture is skipped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use acbc::pcap::{CaptureReader, Direction};
+//!
+//! let reader = CaptureReader::open("acc.pcapng", 9000).unwrap();
+//! for packet in reader {
+//!     let packet = packet.unwrap();
+//!     if let Some(Ok(message)) = packet.inbound() {
+//!         println!("{:?}: {:?}", packet.timestamp, message);
+//!     }
+//! }
+//! ```
+
+use crate::protocol::inbound::InboundMessage;
+use crate::protocol::outbound::ClientMessage;
+use nom_supreme::error::ErrorTree;
+use nom_supreme::final_parser::ByteOffset;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// pcapng block types we care about
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x01;
+const BLOCK_SIMPLE_PACKET: u32 = 0x03;
+const BLOCK_ENHANCED_PACKET: u32 = 0x06;
+
+// Link layer types, see https://www.tcpdump.org/linktypes.html
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// Which way a captured datagram was travelling, relative to the simulator.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    /// Sent by the simulator to a client.
+    Inbound,
+    /// Sent by a client to the simulator.
+    Outbound,
+}
+
+/// A Broadcasting API datagram extracted from a capture.
+#[derive(Debug, Clone)]
+pub struct CapturedPacket {
+    /// Capture time, relative to the Unix epoch.
+    pub timestamp: Duration,
+    pub direction: Direction,
+    /// The UDP payload.
+    pub payload: Vec<u8>,
+}
+
+impl CapturedPacket {
+    /// Decodes the payload of a packet sent by the simulator, returns `None` for outbound packets.
+    pub fn inbound(&self) -> Option<Result<InboundMessage<'_>, ErrorTree<ByteOffset>>> {
+        match self.direction {
+            Direction::Inbound => Some(InboundMessage::decode(&self.payload)),
+            Direction::Outbound => None,
+        }
+    }
+
+    /// Decodes the payload of a packet sent by a client, returns `None` for inbound packets.
+    pub fn outbound(&self) -> Option<Result<ClientMessage<'_>, ErrorTree<ByteOffset>>> {
+        match self.direction {
+            Direction::Inbound => None,
+            Direction::Outbound => Some(ClientMessage::decode(&self.payload)),
+        }
+    }
+}
+
+// A link layer frame read from the capture, before any filtering
+struct Frame {
+    timestamp: u64,
+    interface: Interface,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Interface {
+    link_type: u32,
+    // Timestamp units per second
+    resolution: u64,
+}
+
+enum Format {
+    Pcap {
+        big_endian: bool,
+        interface: Interface,
+    },
+    Pcapng {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+/// Reads Broadcasting API datagrams from a pcap or pcapng capture.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    format: Format,
+    port: u16,
+}
+
+impl CaptureReader<Box<dyn Read + Send>> {
+    /// Opens the capture at `path`, extracting traffic to and from `port`.
+    ///
+    /// Gzip compressed captures are detected automatically when the `gzip` feature is enabled.
+    pub fn open<P: AsRef<Path>>(path: P, port: u16) -> std::io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 2];
+        file.read_exact(&mut magic)?;
+        let file = std::io::Cursor::new(magic).chain(file);
+
+        if magic == GZIP_MAGIC {
+            CaptureReader::new(gunzip(file)?, port)
+        } else {
+            CaptureReader::new(Box::new(file), port)
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip<R: Read + Send + 'static>(reader: R) -> std::io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(flate2::read::GzDecoder::new(reader)))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip<R: Read + Send + 'static>(_reader: R) -> std::io::Result<Box<dyn Read + Send>> {
+    Err(std::io::Error::new(
+        ErrorKind::InvalidData,
+        "capture is gzip compressed, enable the `gzip` feature to read it",
+    ))
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads the capture header from `reader`, extracting traffic to and from `port`.
+    pub fn new(mut reader: R, port: u16) -> std::io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+            let big_endian = read_section_header(&mut reader)?;
+            Format::Pcapng {
+                big_endian,
+                interfaces: vec![],
+            }
+        } else {
+            let (big_endian, nanos) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+                (PCAP_MAGIC_MICROS, _) => (false, false),
+                (PCAP_MAGIC_NANOS, _) => (false, true),
+                (_, PCAP_MAGIC_MICROS) => (true, false),
+                (_, PCAP_MAGIC_NANOS) => (true, true),
+                _ => return Err(invalid("not a pcap or pcapng capture")),
+            };
+            let mut header = [0u8; 20];
+            reader.read_exact(&mut header)?;
+            Format::Pcap {
+                big_endian,
+                interface: Interface {
+                    link_type: read_u32(&header[16..], big_endian),
+                    resolution: if nanos { 1_000_000_000 } else { 1_000_000 },
+                },
+            }
+        };
+
+        Ok(Self {
+            reader,
+            format,
+            port,
+        })
+    }
+
+    /// Returns the next Broadcasting API datagram, or `None` at the end of the capture.
+    pub fn next_packet(&mut self) -> std::io::Result<Option<CapturedPacket>> {
+        loop {
+            let frame = match self.format {
+                Format::Pcap { .. } => self.next_pcap_frame()?,
+                Format::Pcapng { .. } => self.next_pcapng_frame()?,
+            };
+            let frame = match frame {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            let interface = frame.interface;
+            if let Some((direction, payload)) =
+                extract_udp(interface.link_type, &frame.data, self.port)
+            {
+                return Ok(Some(CapturedPacket {
+                    timestamp: to_duration(frame.timestamp, interface.resolution),
+                    direction,
+                    payload: payload.to_vec(),
+                }));
+            }
+        }
+    }
+
+    // `None` at the end of the file
+    fn next_pcap_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        let (big_endian, interface) = match self.format {
+            Format::Pcap {
+                big_endian,
+                interface,
+            } => (big_endian, interface),
+            Format::Pcapng { .. } => unreachable!(),
+        };
+
+        let mut header = [0u8; 16];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let seconds = read_u32(&header[0..], big_endian) as u64;
+        let fraction = read_u32(&header[4..], big_endian) as u64;
+        let captured = read_u32(&header[8..], big_endian) as usize;
+
+        let mut data = vec![0u8; captured];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(Frame {
+            timestamp: seconds * interface.resolution + fraction,
+            interface,
+            data,
+        }))
+    }
+
+    // `None` at the end of the file
+    fn next_pcapng_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.next_pcapng_block()? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    // `None` for blocks which don't contain a packet, `Some(None)` at the end of the file
+    fn next_pcapng_block(&mut self) -> std::io::Result<Option<Option<Frame>>> {
+        let mut header = [0u8; 8];
+        if !read_or_eof(&mut self.reader, &mut header)? {
+            return Ok(Some(None));
+        }
+
+        let block_type = u32::from_le_bytes(header[..4].try_into().unwrap());
+        if block_type == PCAPNG_SECTION_HEADER {
+            // A new section may change byte order and always resets the interface list
+            let mut rest = [0u8; 4];
+            rest.copy_from_slice(&header[4..]);
+            let big_endian = read_section_body(&mut self.reader, rest)?;
+            self.format = Format::Pcapng {
+                big_endian,
+                interfaces: vec![],
+            };
+            return Ok(None);
+        }
+
+        let (big_endian, interfaces) = match &mut self.format {
+            Format::Pcapng {
+                big_endian,
+                interfaces,
+            } => (*big_endian, interfaces),
+            Format::Pcap { .. } => unreachable!(),
+        };
+        let block_type = read_u32(&header[..4], big_endian);
+        let length = read_u32(&header[4..], big_endian) as usize;
+        if length < 12 || length % 4 != 0 {
+            return Err(invalid("malformed pcapng block length"));
+        }
+
+        // Body plus the trailing copy of the block length
+        let mut body = vec![0u8; length - 8];
+        self.reader.read_exact(&mut body)?;
+        body.truncate(length - 12);
+
+        match block_type {
+            BLOCK_INTERFACE_DESCRIPTION => {
+                if body.len() < 8 {
+                    return Err(invalid("truncated interface description block"));
+                }
+                let link_type = read_u16(&body[0..], big_endian) as u32;
+                let resolution = interface_resolution(&body[8..], big_endian);
+                interfaces.push(Interface {
+                    link_type,
+                    resolution,
+                });
+                Ok(None)
+            }
+            BLOCK_ENHANCED_PACKET => {
+                if body.len() < 20 {
+                    return Err(invalid("truncated enhanced packet block"));
+                }
+                let interface = *interfaces
+                    .get(read_u32(&body[0..], big_endian) as usize)
+                    .ok_or_else(|| invalid("packet refers to an unknown interface"))?;
+                let timestamp = (read_u32(&body[4..], big_endian) as u64) << 32
+                    | read_u32(&body[8..], big_endian) as u64;
+                let captured = read_u32(&body[12..], big_endian) as usize;
+                let data = body
+                    .get(20..20 + captured)
+                    .ok_or_else(|| invalid("truncated enhanced packet block"))?;
+                Ok(Some(Some(Frame {
+                    timestamp,
+                    interface,
+                    data: data.to_vec(),
+                })))
+            }
+            BLOCK_SIMPLE_PACKET => {
+                // Simple packets carry no timestamp and always belong to the first interface
+                let interface = *interfaces
+                    .first()
+                    .ok_or_else(|| invalid("packet refers to an unknown interface"))?;
+                let data = body.get(4..).unwrap_or_default();
+                Ok(Some(Some(Frame {
+                    timestamp: 0,
+                    interface,
+                    data: data.to_vec(),
+                })))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = std::io::Result<CapturedPacket>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let bytes = bytes[..2].try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let bytes = bytes[..4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    }
+}
+
+// Fills `buf`, returning false if the reader was already at the end of the file
+fn read_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Reads the rest of a section header block after its type, returning the byte order
+fn read_section_header<R: Read>(reader: &mut R) -> std::io::Result<bool> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    read_section_body(reader, length)
+}
+
+fn read_section_body<R: Read>(reader: &mut R, length: [u8; 4]) -> std::io::Result<bool> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    let big_endian = match u32::from_le_bytes(magic) {
+        PCAPNG_BYTE_ORDER_MAGIC => false,
+        _ if u32::from_be_bytes(magic) == PCAPNG_BYTE_ORDER_MAGIC => true,
+        _ => return Err(invalid("malformed pcapng section header")),
+    };
+
+    let length = read_u32(&length, big_endian) as usize;
+    if length < 28 {
+        return Err(invalid("malformed pcapng section header"));
+    }
+    // Skip the version, section length, options and trailing block length
+    let mut rest = vec![0u8; length - 12];
+    reader.read_exact(&mut rest)?;
+
+    Ok(big_endian)
+}
+
+// Looks for the `if_tsresol` option, defaulting to microseconds
+fn interface_resolution(mut options: &[u8], big_endian: bool) -> u64 {
+    while options.len() >= 4 {
+        let code = read_u16(options, big_endian);
+        let length = read_u16(&options[2..], big_endian) as usize;
+        if code == 0 {
+            break;
+        }
+        if code == 9 && length >= 1 && options.len() > 4 {
+            let value = options[4];
+            let exponent = (value & 0x7f) as u32;
+            return if value & 0x80 == 0 {
+                10u64.saturating_pow(exponent)
+            } else {
+                2u64.saturating_pow(exponent)
+            };
+        }
+        let padded = (length + 3) & !3;
+        options = options.get(4 + padded..).unwrap_or_default();
+    }
+    1_000_000
+}
+
+fn to_duration(timestamp: u64, resolution: u64) -> Duration {
+    let seconds = timestamp / resolution;
+    let nanos = (timestamp % resolution) as u128 * 1_000_000_000 / resolution as u128;
+    Duration::new(seconds, nanos as u32)
+}
+
+// Strips the link, network and transport headers, returning the UDP payload if it's to or from `port`
+fn extract_udp(link_type: u32, frame: &[u8], port: u16) -> Option<(Direction, &[u8])> {
+    let ip = match link_type {
+        LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = u16::from_be_bytes(frame.get(12..14)?.try_into().ok()?);
+            let mut offset = 14;
+            if ethertype == ETHERTYPE_VLAN {
+                ethertype = u16::from_be_bytes(frame.get(16..18)?.try_into().ok()?);
+                offset = 18;
+            }
+            match ethertype {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(offset..)?,
+                _ => return None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+        _ => return None,
+    };
+
+    let udp = match ip.first()? >> 4 {
+        4 => {
+            let header_length = ((ip[0] & 0x0f) as usize) * 4;
+            let total_length = u16::from_be_bytes(ip.get(2..4)?.try_into().ok()?) as usize;
+            let fragment = u16::from_be_bytes(ip.get(6..8)?.try_into().ok()?);
+            // Skip fragments, reassembly isn't supported
+            if *ip.get(9)? != IP_PROTOCOL_UDP || fragment & 0x3fff != 0 {
+                return None;
+            }
+            ip.get(header_length..total_length.min(ip.len()))?
+        }
+        6 => {
+            let payload_length = u16::from_be_bytes(ip.get(4..6)?.try_into().ok()?) as usize;
+            if *ip.get(6)? != IP_PROTOCOL_UDP {
+                return None;
+            }
+            ip.get(40..(40 + payload_length).min(ip.len()))?
+        }
+        _ => return None,
+    };
+
+    let source = u16::from_be_bytes(udp.get(0..2)?.try_into().ok()?);
+    let destination = u16::from_be_bytes(udp.get(2..4)?.try_into().ok()?);
+    let length = u16::from_be_bytes(udp.get(4..6)?.try_into().ok()?) as usize;
+    let payload = udp.get(8..length.min(udp.len()))?;
+
+    if source == port {
+        Some((Direction::Inbound, payload))
+    } else if destination == port {
+        Some((Direction::Outbound, payload))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A classic little-endian pcap with a single Ethernet/IPv4/UDP frame
+    fn ethernet_pcap(source: u16, destination: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+        let udp_length = 8 + payload.len() as u16;
+        frame.extend_from_slice(&[0x45, 0x00]);
+        frame.extend_from_slice(&(20 + udp_length).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, IP_PROTOCOL_UDP, 0, 0]);
+        frame.extend_from_slice(&[127, 0, 0, 1, 127, 0, 0, 1]);
+        frame.extend_from_slice(&source.to_be_bytes());
+        frame.extend_from_slice(&destination.to_be_bytes());
+        frame.extend_from_slice(&udp_length.to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(payload);
+
+        let mut capture = vec![];
+        capture.extend_from_slice(&PCAP_MAGIC_MICROS.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        capture.extend_from_slice(&65535u32.to_le_bytes());
+        capture.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        capture.extend_from_slice(&10u32.to_le_bytes());
+        capture.extend_from_slice(&500u32.to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&frame);
+        capture
+    }
+
+    #[test]
+    fn reads_classic_pcap() {
+        let payload = [0x04, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03];
+        let capture = ethernet_pcap(9000, 50123, &payload);
+        let mut reader = CaptureReader::new(Cursor::new(capture), 9000).unwrap();
+
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.direction, Direction::Inbound);
+        assert_eq!(packet.timestamp, Duration::new(10, 500_000));
+        assert!(matches!(
+            packet.inbound(),
+            Some(Ok(InboundMessage::EntrylistUpdate(_)))
+        ));
+        assert!(reader.next_packet().unwrap().is_none());
+    }
+
+    #[test]
+    fn skips_other_ports() {
+        let capture = ethernet_pcap(53, 50123, b"not broadcast traffic");
+        let reader = CaptureReader::new(Cursor::new(capture), 9000).unwrap();
+        assert_eq!(reader.count(), 0);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn reads_example_capture() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/docs/pcap/acc.pcapng.gz");
+        let packets = CaptureReader::open(path, 9000)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        // The capture contains about 180 datagrams, almost all sent by the simulator
+        assert_eq!(packets.len(), 181);
+        let outbound = packets
+            .iter()
+            .filter(|p| p.direction == Direction::Outbound)
+            .count();
+        assert_eq!(outbound, 3);
+        // The first datagram of the session is the client's registration request
+        assert!(matches!(
+            packets[0].outbound(),
+            Some(Ok(ClientMessage::RegistrationRequest(_)))
+        ));
+
+        for packet in &packets {
+            match packet.direction {
+                Direction::Inbound => assert!(packet.inbound().unwrap().is_ok()),
+                Direction::Outbound => assert!(packet.outbound().unwrap().is_ok()),
+            }
+        }
+    }
+}
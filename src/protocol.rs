@@ -1,15 +1,29 @@
 use thiserror::Error;
 
 pub mod acc_enum;
+mod encoder;
 pub mod inbound;
 pub mod outbound;
 mod parser;
 
-const PROTOCOL_VERSION: u8 = 4;
+pub(crate) const PROTOCOL_VERSION: u8 = 4;
 
 pub use inbound::*;
 pub use outbound::*;
 
+/// How enum fields with values unknown to this crate are handled when decoding.
+///
+/// New simulator releases regularly add car models and the like. In [`Lenient`](DecodeMode::Lenient)
+/// mode, `CarModel`, `Nationality`, `CupCategory`, `DriverCategory` and `BroadcastingEventType`
+/// values which aren't recognised decode to their `Unknown` variant instead of failing the whole
+/// packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DecodeMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
 #[derive(Debug, Error)]
 pub enum DecodeError {
     #[error("Unrecognised session type `{0}`")]
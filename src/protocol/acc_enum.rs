@@ -11,6 +11,7 @@ use std::fmt::{Display, Formatter};
 
 /// The type of session the connected simulator is running.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SessionType {
     Practice,
     Qualifying,
@@ -40,8 +41,24 @@ impl TryFrom<u8> for SessionType {
     }
 }
 
+impl From<SessionType> for u8 {
+    fn from(value: SessionType) -> Self {
+        match value {
+            SessionType::Practice => 0,
+            SessionType::Qualifying => 4,
+            SessionType::Superpole => 9,
+            SessionType::Race => 10,
+            SessionType::Hotlap => 11,
+            SessionType::Hotstint => 12,
+            SessionType::HotlapSuperpole => 13,
+            SessionType::Replay => 14,
+        }
+    }
+}
+
 /// The phase of the simulator's current session.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SessionPhase {
     /// The simulator itself has not started yet, rarely seen.
     None,
@@ -83,8 +100,25 @@ impl TryFrom<u8> for SessionPhase {
     }
 }
 
+impl From<SessionPhase> for u8 {
+    fn from(value: SessionPhase) -> Self {
+        match value {
+            SessionPhase::None => 0,
+            SessionPhase::Starting => 1,
+            SessionPhase::PreFormation => 2,
+            SessionPhase::FormationLap => 3,
+            SessionPhase::PreSession => 4,
+            SessionPhase::Session => 5,
+            SessionPhase::SessionOver => 6,
+            SessionPhase::PostSession => 7,
+            SessionPhase::ResultUi => 8,
+        }
+    }
+}
+
 /// The current location of a car.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CarLocation {
     None,
     Track,
@@ -108,8 +142,31 @@ impl TryFrom<u8> for CarLocation {
     }
 }
 
+impl CarLocation {
+    /// Whether the car is anywhere in the pit lane, including the entry and exit.
+    pub fn in_pits(self) -> bool {
+        matches!(
+            self,
+            CarLocation::Pitlane | CarLocation::PitEntry | CarLocation::PitExit
+        )
+    }
+}
+
+impl From<CarLocation> for u8 {
+    fn from(value: CarLocation) -> Self {
+        match value {
+            CarLocation::None => 0,
+            CarLocation::Track => 1,
+            CarLocation::Pitlane => 2,
+            CarLocation::PitEntry => 3,
+            CarLocation::PitExit => 4,
+        }
+    }
+}
+
 /// The nationality of a Car or Driver.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Nationality {
     Any,
     Italy,
@@ -189,6 +246,8 @@ pub enum Nationality {
     Ukraine,
     Venezuela,
     Wales,
+    /// A nationality not known to this version of the crate, only produced by lenient decoding.
+    Unknown(u16),
 }
 
 impl TryFrom<u16> for Nationality {
@@ -279,9 +338,105 @@ impl TryFrom<u16> for Nationality {
     }
 }
 
+impl Nationality {
+    /// Converts a raw value, falling back to [`Nationality::Unknown`] rather than failing.
+    pub fn from_raw(value: u16) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
+}
+
+impl From<Nationality> for u16 {
+    fn from(value: Nationality) -> Self {
+        match value {
+            Nationality::Any => 0,
+            Nationality::Italy => 1,
+            Nationality::Germany => 2,
+            Nationality::France => 3,
+            Nationality::Spain => 4,
+            Nationality::GreatBritain => 5,
+            Nationality::Hungary => 6,
+            Nationality::Belgium => 7,
+            Nationality::Switzerland => 8,
+            Nationality::Austria => 9,
+            Nationality::Russia => 10,
+            Nationality::Thailand => 11,
+            Nationality::Netherlands => 12,
+            Nationality::Poland => 13,
+            Nationality::Argentina => 14,
+            Nationality::Monaco => 15,
+            Nationality::Ireland => 16,
+            Nationality::Brazil => 17,
+            Nationality::SouthAfrica => 18,
+            Nationality::PuertoRico => 19,
+            Nationality::Slovakia => 20,
+            Nationality::Oman => 21,
+            Nationality::Greece => 22,
+            Nationality::SaudiArabia => 23,
+            Nationality::Norway => 24,
+            Nationality::Turkey => 25,
+            Nationality::SouthKorea => 26,
+            Nationality::Lebanon => 27,
+            Nationality::Armenia => 28,
+            Nationality::Mexico => 29,
+            Nationality::Sweden => 30,
+            Nationality::Finland => 31,
+            Nationality::Denmark => 32,
+            Nationality::Croatia => 33,
+            Nationality::Canada => 34,
+            Nationality::China => 35,
+            Nationality::Portugal => 36,
+            Nationality::Singapore => 37,
+            Nationality::Indonesia => 38,
+            Nationality::Usa => 39,
+            Nationality::NewZealand => 40,
+            Nationality::Australia => 41,
+            Nationality::SanMarino => 42,
+            Nationality::Uae => 43,
+            Nationality::Luxembourg => 44,
+            Nationality::Kuwait => 45,
+            Nationality::HongKong => 46,
+            Nationality::Colombia => 47,
+            Nationality::Japan => 48,
+            Nationality::Andorra => 49,
+            Nationality::Azerbaijan => 50,
+            Nationality::Bulgaria => 51,
+            Nationality::Cuba => 52,
+            Nationality::CzechRepublic => 53,
+            Nationality::Estonia => 54,
+            Nationality::Georgia => 55,
+            Nationality::India => 56,
+            Nationality::Israel => 57,
+            Nationality::Jamaica => 58,
+            Nationality::Latvia => 59,
+            Nationality::Lithuania => 60,
+            Nationality::Macau => 61,
+            Nationality::Malaysia => 62,
+            Nationality::Nepal => 63,
+            Nationality::NewCaledonia => 64,
+            Nationality::Nigeria => 65,
+            Nationality::NorthernIreland => 66,
+            Nationality::PapuaNewGuinea => 67,
+            Nationality::Philippines => 68,
+            Nationality::Qatar => 69,
+            Nationality::Romania => 70,
+            Nationality::Scotland => 71,
+            Nationality::Serbia => 72,
+            Nationality::Slovenia => 73,
+            Nationality::Taiwan => 74,
+            Nationality::Ukraine => 75,
+            Nationality::Venezuela => 76,
+            Nationality::Wales => 77,
+            Nationality::Unknown(x) => x,
+        }
+    }
+}
+
 /// A selected Car Model.
+///
+/// Use [`info`](CarModel::info) to look up the class, manufacturer, name and year of a model.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CarModel {
     Porsche911,
     MercedesAMG,
@@ -309,6 +464,17 @@ pub enum CarModel {
     Porsche911_2,
     Ferrari488Evo,
     MercedesAMGEvo,
+    Ferrari488Challenge,
+    BMWM2CS,
+    Porsche992Cup,
+    LamborghiniSuperTrofeoEvo2,
+    BMWM4GT3,
+    AudiR8LMSEvo2,
+    Ferrari296GT3,
+    LamborghiniHuracanEvo2,
+    Porsche992GT3R,
+    McLaren720SEvo,
+    FordMustangGT3,
 
     AlpineA1110,
     AstonMartinVantageGT4,
@@ -321,6 +487,15 @@ pub enum CarModel {
     McLaren570SGT4,
     MercedesAMGGT4,
     Porsche718GT4,
+
+    AudiR8LMSGT2,
+    KTMXBowGT2,
+    MaseratiMC20GT2,
+    MercedesAMGGT2,
+    Porsche911GT2RS,
+    Porsche935,
+    /// A car model not known to this version of the crate, only produced by lenient decoding.
+    Unknown(u8),
 }
 
 impl TryFrom<u8> for CarModel {
@@ -354,6 +529,17 @@ impl TryFrom<u8> for CarModel {
             23 => Ok(CarModel::Porsche911_2),
             24 => Ok(CarModel::Ferrari488Evo),
             25 => Ok(CarModel::MercedesAMGEvo),
+            26 => Ok(CarModel::Ferrari488Challenge),
+            27 => Ok(CarModel::BMWM2CS),
+            28 => Ok(CarModel::Porsche992Cup),
+            29 => Ok(CarModel::LamborghiniSuperTrofeoEvo2),
+            30 => Ok(CarModel::BMWM4GT3),
+            31 => Ok(CarModel::AudiR8LMSEvo2),
+            32 => Ok(CarModel::Ferrari296GT3),
+            33 => Ok(CarModel::LamborghiniHuracanEvo2),
+            34 => Ok(CarModel::Porsche992GT3R),
+            35 => Ok(CarModel::McLaren720SEvo),
+            36 => Ok(CarModel::FordMustangGT3),
 
             50 => Ok(CarModel::AlpineA1110),
             51 => Ok(CarModel::AstonMartinVantageGT4),
@@ -366,54 +552,281 @@ impl TryFrom<u8> for CarModel {
             59 => Ok(CarModel::McLaren570SGT4),
             60 => Ok(CarModel::MercedesAMGGT4),
             61 => Ok(CarModel::Porsche718GT4),
+
+            80 => Ok(CarModel::AudiR8LMSGT2),
+            82 => Ok(CarModel::KTMXBowGT2),
+            83 => Ok(CarModel::MaseratiMC20GT2),
+            84 => Ok(CarModel::MercedesAMGGT2),
+            85 => Ok(CarModel::Porsche911GT2RS),
+            86 => Ok(CarModel::Porsche935),
             x => Err(DecodeError::UnknownCarModel(x)),
         }
     }
 }
 
+impl CarModel {
+    /// Converts a raw value, falling back to [`CarModel::Unknown`] rather than failing.
+    pub fn from_raw(value: u8) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
+
+    /// Details of the car model, `None` for [`CarModel::Unknown`].
+    pub fn info(&self) -> Option<CarModelInfo> {
+        let (class, manufacturer, name, year) = match self {
+            CarModel::Porsche911 => (CarClass::GT3, "Porsche", "Porsche 991 GT3 R", 2018),
+            CarModel::MercedesAMG => (CarClass::GT3, "Mercedes-AMG", "Mercedes-AMG GT3", 2015),
+            CarModel::Ferrari488 => (CarClass::GT3, "Ferrari", "Ferrari 488 GT3", 2018),
+            CarModel::AudiR8LMS => (CarClass::GT3, "Audi", "Audi R8 LMS", 2015),
+            CarModel::LamborghiniHuracan => (
+                CarClass::GT3,
+                "Lamborghini",
+                "Lamborghini Huracán GT3",
+                2015,
+            ),
+            CarModel::McLaren650S => (CarClass::GT3, "McLaren", "McLaren 650S GT3", 2015),
+            CarModel::NissanGTR2018 => (CarClass::GT3, "Nissan", "Nissan GT-R Nismo GT3", 2018),
+            CarModel::BMWM6 => (CarClass::GT3, "BMW", "BMW M6 GT3", 2017),
+            CarModel::BentleyContinental2018 => {
+                (CarClass::GT3, "Bentley", "Bentley Continental GT3", 2018)
+            }
+            CarModel::Porsche911Cup => (CarClass::Cup, "Porsche", "Porsche 991 II GT3 Cup", 2017),
+            CarModel::NissanGTR2017 => (CarClass::GT3, "Nissan", "Nissan GT-R Nismo GT3", 2015),
+            CarModel::BentleyContinental2016 => {
+                (CarClass::GT3, "Bentley", "Bentley Continental GT3", 2015)
+            }
+            CarModel::AstonMartinVantageV12 => (
+                CarClass::GT3,
+                "Aston Martin",
+                "Aston Martin V12 Vantage GT3",
+                2013,
+            ),
+            CarModel::LamborghiniGallardo => (
+                CarClass::GT3,
+                "Reiter Engineering",
+                "Reiter Engineering R-EX GT3",
+                2017,
+            ),
+            CarModel::JaguarG3 => (CarClass::GT3, "Jaguar", "Emil Frey Jaguar G3", 2012),
+            CarModel::LexusRCF => (CarClass::GT3, "Lexus", "Lexus RC F GT3", 2016),
+            CarModel::LamborghiniHuracanEvo => (
+                CarClass::GT3,
+                "Lamborghini",
+                "Lamborghini Huracán GT3 Evo",
+                2019,
+            ),
+            CarModel::HondaNSX => (CarClass::GT3, "Honda", "Honda NSX GT3", 2017),
+            CarModel::LamborghiniSuperTrofeo => (
+                CarClass::SuperTrofeo,
+                "Lamborghini",
+                "Lamborghini Huracán Super Trofeo",
+                2015,
+            ),
+            CarModel::AudiR8LMSEvo => (CarClass::GT3, "Audi", "Audi R8 LMS Evo", 2019),
+            CarModel::AstonMartinVantageV8 => (
+                CarClass::GT3,
+                "Aston Martin",
+                "Aston Martin V8 Vantage GT3",
+                2019,
+            ),
+            CarModel::HondaNSXEvo => (CarClass::GT3, "Honda", "Honda NSX GT3 Evo", 2019),
+            CarModel::McLaren720S => (CarClass::GT3, "McLaren", "McLaren 720S GT3", 2019),
+            CarModel::Porsche911_2 => (CarClass::GT3, "Porsche", "Porsche 991 II GT3 R", 2019),
+            CarModel::Ferrari488Evo => (CarClass::GT3, "Ferrari", "Ferrari 488 GT3 Evo", 2020),
+            CarModel::MercedesAMGEvo => {
+                (CarClass::GT3, "Mercedes-AMG", "Mercedes-AMG GT3 Evo", 2020)
+            }
+            CarModel::Ferrari488Challenge => (
+                CarClass::Challenge,
+                "Ferrari",
+                "Ferrari 488 Challenge Evo",
+                2020,
+            ),
+            CarModel::BMWM2CS => (CarClass::TCX, "BMW", "BMW M2 CS Racing", 2020),
+            CarModel::Porsche992Cup => (CarClass::Cup, "Porsche", "Porsche 992 GT3 Cup", 2021),
+            CarModel::LamborghiniSuperTrofeoEvo2 => (
+                CarClass::SuperTrofeo,
+                "Lamborghini",
+                "Lamborghini Huracán Super Trofeo Evo2",
+                2021,
+            ),
+            CarModel::BMWM4GT3 => (CarClass::GT3, "BMW", "BMW M4 GT3", 2021),
+            CarModel::AudiR8LMSEvo2 => (CarClass::GT3, "Audi", "Audi R8 LMS GT3 Evo II", 2022),
+            CarModel::Ferrari296GT3 => (CarClass::GT3, "Ferrari", "Ferrari 296 GT3", 2023),
+            CarModel::LamborghiniHuracanEvo2 => (
+                CarClass::GT3,
+                "Lamborghini",
+                "Lamborghini Huracán GT3 Evo2",
+                2023,
+            ),
+            CarModel::Porsche992GT3R => (CarClass::GT3, "Porsche", "Porsche 992 GT3 R", 2023),
+            CarModel::McLaren720SEvo => (CarClass::GT3, "McLaren", "McLaren 720S GT3 Evo", 2023),
+            CarModel::FordMustangGT3 => (CarClass::GT3, "Ford", "Ford Mustang GT3", 2024),
+
+            CarModel::AlpineA1110 => (CarClass::GT4, "Alpine", "Alpine A110 GT4", 2018),
+            CarModel::AstonMartinVantageGT4 => (
+                CarClass::GT4,
+                "Aston Martin",
+                "Aston Martin V8 Vantage GT4",
+                2018,
+            ),
+            CarModel::AudiR8LMSGT4 => (CarClass::GT4, "Audi", "Audi R8 LMS GT4", 2018),
+            CarModel::BMWM4GT4 => (CarClass::GT4, "BMW", "BMW M4 GT4", 2018),
+            CarModel::ChevroletCamaroGT4 => {
+                (CarClass::GT4, "Chevrolet", "Chevrolet Camaro GT4.R", 2017)
+            }
+            CarModel::GinettaG55GT4 => (CarClass::GT4, "Ginetta", "Ginetta G55 GT4", 2012),
+            CarModel::KTMXBowGT4 => (CarClass::GT4, "KTM", "KTM X-Bow GT4", 2016),
+            CarModel::MaseratiMCGT4 => (CarClass::GT4, "Maserati", "Maserati MC GT4", 2016),
+            CarModel::McLaren570SGT4 => (CarClass::GT4, "McLaren", "McLaren 570S GT4", 2016),
+            CarModel::MercedesAMGGT4 => (CarClass::GT4, "Mercedes-AMG", "Mercedes-AMG GT4", 2016),
+            CarModel::Porsche718GT4 => (
+                CarClass::GT4,
+                "Porsche",
+                "Porsche 718 Cayman GT4 Clubsport",
+                2019,
+            ),
+
+            CarModel::AudiR8LMSGT2 => (CarClass::GT2, "Audi", "Audi R8 LMS GT2", 2021),
+            CarModel::KTMXBowGT2 => (CarClass::GT2, "KTM", "KTM X-Bow GT2", 2021),
+            CarModel::MaseratiMC20GT2 => (CarClass::GT2, "Maserati", "Maserati MC20 GT2", 2023),
+            CarModel::MercedesAMGGT2 => (CarClass::GT2, "Mercedes-AMG", "Mercedes-AMG GT2", 2023),
+            CarModel::Porsche911GT2RS => {
+                (CarClass::GT2, "Porsche", "Porsche 911 GT2 RS CS Evo", 2023)
+            }
+            CarModel::Porsche935 => (CarClass::GT2, "Porsche", "Porsche 935", 2019),
+            CarModel::Unknown(_) => return None,
+        };
+        Some(CarModelInfo {
+            class,
+            manufacturer,
+            name,
+            year,
+        })
+    }
+
+    /// The class the car races in, `None` for [`CarModel::Unknown`].
+    pub fn class(&self) -> Option<CarClass> {
+        self.info().map(|info| info.class)
+    }
+}
+
+impl From<CarModel> for u8 {
+    fn from(value: CarModel) -> Self {
+        match value {
+            CarModel::Porsche911 => 0,
+            CarModel::MercedesAMG => 1,
+            CarModel::Ferrari488 => 2,
+            CarModel::AudiR8LMS => 3,
+            CarModel::LamborghiniHuracan => 4,
+            CarModel::McLaren650S => 5,
+            CarModel::NissanGTR2018 => 6,
+            CarModel::BMWM6 => 7,
+            CarModel::BentleyContinental2018 => 8,
+            CarModel::Porsche911Cup => 9,
+            CarModel::NissanGTR2017 => 10,
+            CarModel::BentleyContinental2016 => 11,
+            CarModel::AstonMartinVantageV12 => 12,
+            CarModel::LamborghiniGallardo => 13,
+            CarModel::JaguarG3 => 14,
+            CarModel::LexusRCF => 15,
+            CarModel::LamborghiniHuracanEvo => 16,
+            CarModel::HondaNSX => 17,
+            CarModel::LamborghiniSuperTrofeo => 18,
+            CarModel::AudiR8LMSEvo => 19,
+            CarModel::AstonMartinVantageV8 => 20,
+            CarModel::HondaNSXEvo => 21,
+            CarModel::McLaren720S => 22,
+            CarModel::Porsche911_2 => 23,
+            CarModel::Ferrari488Evo => 24,
+            CarModel::MercedesAMGEvo => 25,
+            CarModel::Ferrari488Challenge => 26,
+            CarModel::BMWM2CS => 27,
+            CarModel::Porsche992Cup => 28,
+            CarModel::LamborghiniSuperTrofeoEvo2 => 29,
+            CarModel::BMWM4GT3 => 30,
+            CarModel::AudiR8LMSEvo2 => 31,
+            CarModel::Ferrari296GT3 => 32,
+            CarModel::LamborghiniHuracanEvo2 => 33,
+            CarModel::Porsche992GT3R => 34,
+            CarModel::McLaren720SEvo => 35,
+            CarModel::FordMustangGT3 => 36,
+
+            CarModel::AlpineA1110 => 50,
+            CarModel::AstonMartinVantageGT4 => 51,
+            CarModel::AudiR8LMSGT4 => 52,
+            CarModel::BMWM4GT4 => 53,
+            CarModel::ChevroletCamaroGT4 => 55,
+            CarModel::GinettaG55GT4 => 56,
+            CarModel::KTMXBowGT4 => 57,
+            CarModel::MaseratiMCGT4 => 58,
+            CarModel::McLaren570SGT4 => 59,
+            CarModel::MercedesAMGGT4 => 60,
+            CarModel::Porsche718GT4 => 61,
+
+            CarModel::AudiR8LMSGT2 => 80,
+            CarModel::KTMXBowGT2 => 82,
+            CarModel::MaseratiMC20GT2 => 83,
+            CarModel::MercedesAMGGT2 => 84,
+            CarModel::Porsche911GT2RS => 85,
+            CarModel::Porsche935 => 86,
+            CarModel::Unknown(x) => x,
+        }
+    }
+}
+
+// The year keeps models which share a name, such as the two generations of Nissan GT-R, apart
 impl Display for CarModel {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let str_name = match self {
-            CarModel::Porsche911 => "Porsche 911 GT3",
-            CarModel::MercedesAMG => "Mercedes AMG GT3",
-            CarModel::Ferrari488 => "Ferrari 488 GT3",
-            CarModel::AudiR8LMS => "Audi R8 LMS",
-            CarModel::LamborghiniHuracan => "Lamborghini Huracan GT3",
-            CarModel::McLaren650S => "McLaren 650s GT3",
-            CarModel::NissanGTR2018 => "Nissan GT R Nismo GT3 2018",
-            CarModel::BMWM6 => "BMW M6 GT3",
-            CarModel::BentleyContinental2018 => "Bentley Continental GT3 2018",
-            CarModel::Porsche911Cup => "Porsche 991.2 GT3 Cup",
-            CarModel::NissanGTR2017 => "Nissan GT-R Nismo GT3 2017",
-            CarModel::BentleyContinental2016 => "Bentley Continental GT3 2016",
-            CarModel::AstonMartinVantageV12 => "Aston Martin Vantage V12 GT3",
-            CarModel::LamborghiniGallardo => "Lamborghini Gallardo R-EX",
-            CarModel::JaguarG3 => "Jaguar G3",
-            CarModel::LexusRCF => "Lexus RC F GT3",
-            CarModel::LamborghiniHuracanEvo => "Lamborghini Huracan Evo (2019)",
-            CarModel::HondaNSX => "Honda NSX GT3",
-            CarModel::LamborghiniSuperTrofeo => "Lamborghini Huracan SuperTrofeo",
-            CarModel::AudiR8LMSEvo => "Audi R8 LMS Evo (2019)",
-            CarModel::AstonMartinVantageV8 => "AMR V8 Vantage (2019)",
-            CarModel::HondaNSXEvo => "Honda NSX Evo (2019)",
-            CarModel::McLaren720S => "McLaren 720S GT3 (2019)",
-            CarModel::Porsche911_2 => "Porsche 911 II GT3 R (2019)",
-            CarModel::Ferrari488Evo => "Ferrari 488 GT3 Evo 2020",
-            CarModel::MercedesAMGEvo => "Mercedes-AMG GT3 Evo 2020",
-
-            CarModel::AlpineA1110 => "Alpine A1110 GT4",
-            CarModel::AstonMartinVantageGT4 => "Aston Martin Vantage GT4",
-            CarModel::AudiR8LMSGT4 => "Audi R8 LMS GT4",
-            CarModel::BMWM4GT4 => "BMW M4 GT4",
-            CarModel::ChevroletCamaroGT4 => "Chevrolet Camaro GT4",
-            CarModel::GinettaG55GT4 => "Ginetta G55 GT4",
-            CarModel::KTMXBowGT4 => "KTM X-Bow GT4",
-            CarModel::MaseratiMCGT4 => "Maserati MC GT4",
-            CarModel::McLaren570SGT4 => "McLaren 570S GT4",
-            CarModel::MercedesAMGGT4 => "Mercedes AMG GT4",
-            CarModel::Porsche718GT4 => "Porsche 718 Cayman GT4",
+        match self.info() {
+            Some(info) => write!(f, "{} {}", info.name, info.year),
+            None => write!(f, "Unknown car model ({})", u8::from(*self)),
+        }
+    }
+}
+
+/// Static details of a [`CarModel`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CarModelInfo {
+    pub class: CarClass,
+    pub manufacturer: &'static str,
+    /// The name shown by the simulator, including the manufacturer.
+    pub name: &'static str,
+    /// The model year of the car's specification.
+    pub year: u16,
+}
+
+/// The class a car model races in.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CarClass {
+    GT3,
+    GT4,
+    GT2,
+    /// Porsche Carrera Cup cars.
+    Cup,
+    /// Lamborghini Super Trofeo cars.
+    SuperTrofeo,
+    /// Ferrari Challenge cars.
+    Challenge,
+    /// BMW M2 CS Racing cars.
+    TCX,
+}
+
+impl Display for CarClass {
+    /// Writes the short badge used by the simulator, such as `GT3` or `CHL`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let badge = match self {
+            CarClass::GT3 => "GT3",
+            CarClass::GT4 => "GT4",
+            CarClass::GT2 => "GT2",
+            CarClass::Cup => "CUP",
+            CarClass::SuperTrofeo => "ST",
+            CarClass::Challenge => "CHL",
+            CarClass::TCX => "TCX",
         };
-        write!(f, "{}", str_name)
+        write!(f, "{}", badge)
     }
 }
 
@@ -423,11 +836,14 @@ impl Display for CarModel {
 /// - `Silver` = Silver Badge, SILVER Class
 /// - `Bronze` = Red Badge, AM Class
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DriverCategory {
     Platinum,
     Gold,
     Silver,
     Bronze,
+    /// A driver category not known to this version of the crate, only produced by lenient decoding.
+    Unknown(u8),
 }
 
 impl TryFrom<u8> for DriverCategory {
@@ -444,6 +860,25 @@ impl TryFrom<u8> for DriverCategory {
     }
 }
 
+impl DriverCategory {
+    /// Converts a raw value, falling back to [`DriverCategory::Unknown`] rather than failing.
+    pub fn from_raw(value: u8) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
+}
+
+impl From<DriverCategory> for u8 {
+    fn from(value: DriverCategory) -> Self {
+        match value {
+            DriverCategory::Platinum => 3,
+            DriverCategory::Gold => 2,
+            DriverCategory::Silver => 1,
+            DriverCategory::Bronze => 0,
+            DriverCategory::Unknown(x) => x,
+        }
+    }
+}
+
 /// The class or category of a car in a session.
 ///
 /// The categories which appear in normal sessions seem to be:
@@ -452,13 +887,16 @@ impl TryFrom<u8> for DriverCategory {
 /// - Red Badge = `Am`
 ///
 /// The other categories here, `ProAm` and `National` possibly only appear in single-player campaign modes.
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CupCategory {
     Overall,
     ProAm,
     Am,
     Silver,
     National,
+    /// A cup category not known to this version of the crate, only produced by lenient decoding.
+    Unknown(u8),
 }
 
 impl TryFrom<u8> for CupCategory {
@@ -476,8 +914,29 @@ impl TryFrom<u8> for CupCategory {
     }
 }
 
+impl CupCategory {
+    /// Converts a raw value, falling back to [`CupCategory::Unknown`] rather than failing.
+    pub fn from_raw(value: u8) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
+}
+
+impl From<CupCategory> for u8 {
+    fn from(value: CupCategory) -> Self {
+        match value {
+            CupCategory::Overall => 0,
+            CupCategory::ProAm => 1,
+            CupCategory::Am => 2,
+            CupCategory::Silver => 3,
+            CupCategory::National => 4,
+            CupCategory::Unknown(x) => x,
+        }
+    }
+}
+
 /// The type of an event relevant to the broadcast.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BroadcastingEventType {
     /// No specific type, message may still be populated with information.
     None,
@@ -489,6 +948,8 @@ pub enum BroadcastingEventType {
     LapCompleted,
     BestSessionLap,
     BestPersonalLap,
+    /// An event type not known to this version of the crate, only produced by lenient decoding.
+    Unknown(u8),
 }
 
 impl TryFrom<u8> for BroadcastingEventType {
@@ -508,3 +969,74 @@ impl TryFrom<u8> for BroadcastingEventType {
         }
     }
 }
+
+impl BroadcastingEventType {
+    /// Converts a raw value, falling back to [`BroadcastingEventType::Unknown`] rather than failing.
+    pub fn from_raw(value: u8) -> Self {
+        Self::try_from(value).unwrap_or(Self::Unknown(value))
+    }
+}
+
+impl From<BroadcastingEventType> for u8 {
+    fn from(value: BroadcastingEventType) -> Self {
+        match value {
+            BroadcastingEventType::None => 0,
+            BroadcastingEventType::GreenFlag => 1,
+            BroadcastingEventType::SessionOver => 2,
+            BroadcastingEventType::PenaltyMessage => 3,
+            BroadcastingEventType::Accident => 4,
+            BroadcastingEventType::LapCompleted => 5,
+            BroadcastingEventType::BestSessionLap => 6,
+            BroadcastingEventType::BestPersonalLap => 7,
+            BroadcastingEventType::Unknown(x) => x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_car_model_has_info() {
+        for raw in 0..=u8::MAX {
+            if let Ok(model) = CarModel::try_from(raw) {
+                assert_eq!(u8::from(model), raw);
+                assert!(model.info().is_some(), "{:?} has no info", model);
+            }
+        }
+        assert!(CarModel::Unknown(200).info().is_none());
+    }
+
+    #[test]
+    fn car_model_names_are_unique() {
+        let mut names = std::collections::HashSet::new();
+        for raw in 0..=u8::MAX {
+            let model = CarModel::from_raw(raw);
+            assert!(names.insert(model.to_string()), "{} is not unique", model);
+        }
+        assert_eq!(
+            CarModel::NissanGTR2017.to_string(),
+            "Nissan GT-R Nismo GT3 2015"
+        );
+        assert_eq!(
+            CarModel::NissanGTR2018.to_string(),
+            "Nissan GT-R Nismo GT3 2018"
+        );
+    }
+
+    #[test]
+    fn car_model_metadata() {
+        let info = CarModel::from_raw(32).info().unwrap();
+        assert_eq!(info.class, CarClass::GT3);
+        assert_eq!(info.manufacturer, "Ferrari");
+        assert_eq!(info.year, 2023);
+        assert_eq!(CarModel::Ferrari296GT3.to_string(), "Ferrari 296 GT3 2023");
+
+        assert_eq!(CarModel::Porsche935.class(), Some(CarClass::GT2));
+        assert_eq!(
+            CarModel::Ferrari488Challenge.class().unwrap().to_string(),
+            "CHL"
+        );
+    }
+}
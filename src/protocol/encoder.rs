@@ -0,0 +1,549 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Write};
+
+use crate::protocol::inbound::{
+    BroadcastingEvent, Driver, EntrylistCar, EntrylistUpdate, InboundMessage, Lap,
+    RealtimeCarUpdate, RealtimeUpdate, RegistrationResult, ReplayInfo, TrackData,
+};
+use crate::protocol::outbound::write_kstring;
+
+pub(crate) fn encode<W: Write>(
+    message: &InboundMessage<'_>,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    match message {
+        InboundMessage::RegistrationResult(result) => registration_result(result, writer),
+        InboundMessage::RealtimeUpdate(update) => realtime_update(update, writer),
+        InboundMessage::RealtimeCarUpdate(update) => realtime_car_update(update, writer),
+        InboundMessage::EntrylistUpdate(update) => entrylist_update(update, writer),
+        InboundMessage::EntrylistCar(car) => entrylist_car(car, writer),
+        InboundMessage::TrackData(data) => track_data(data, writer),
+        InboundMessage::BroadcastingEvent(event) => broadcasting_event(event, writer),
+    }
+}
+
+// Counts of repeated fields are written as a narrow integer, so refuse anything which won't fit
+fn count<T: TryFrom<usize>>(len: usize, field: &str) -> std::io::Result<T> {
+    T::try_from(len).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("too many {} to encode", field),
+        )
+    })
+}
+
+fn boolean<W: Write>(value: bool, writer: &mut W) -> std::io::Result<()> {
+    writer.write_u8(value as u8)
+}
+
+fn registration_result<W: Write>(
+    result: &RegistrationResult<'_>,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writer.write_u8(0x01)?;
+    writer.write_u32::<LittleEndian>(result.connection_id)?;
+    boolean(result.connection_success, writer)?;
+    boolean(result.read_only, writer)?;
+    write_kstring(&result.error_message, writer)
+}
+
+fn entrylist_update<W: Write>(update: &EntrylistUpdate, writer: &mut W) -> std::io::Result<()> {
+    writer.write_u8(0x04)?;
+    writer.write_u32::<LittleEndian>(update.connection_id)?;
+    writer.write_u16::<LittleEndian>(count(update.car_ids.len(), "car IDs")?)?;
+    for id in &update.car_ids {
+        writer.write_u16::<LittleEndian>(*id)?;
+    }
+    Ok(())
+}
+
+fn replay_info<W: Write>(info: &Option<ReplayInfo>, writer: &mut W) -> std::io::Result<()> {
+    match info {
+        None => writer.write_u8(0x00),
+        Some(info) => {
+            writer.write_u8(0x01)?;
+            writer.write_f32::<LittleEndian>(info.session_time)?;
+            writer.write_f32::<LittleEndian>(info.remaining_time)?;
+            writer.write_u32::<LittleEndian>(info.focused_car_index)
+        }
+    }
+}
+
+fn driver<W: Write>(driver: &Driver<'_>, writer: &mut W) -> std::io::Result<()> {
+    write_kstring(&driver.first_name, writer)?;
+    write_kstring(&driver.last_name, writer)?;
+    write_kstring(&driver.short_name, writer)?;
+    writer.write_u8(driver.category.into())?;
+    writer.write_u16::<LittleEndian>(driver.nationality.into())
+}
+
+fn entrylist_car<W: Write>(car: &EntrylistCar<'_>, writer: &mut W) -> std::io::Result<()> {
+    writer.write_u8(0x06)?;
+    writer.write_u16::<LittleEndian>(car.id)?;
+    writer.write_u8(car.model.into())?;
+    write_kstring(&car.team_name, writer)?;
+    writer.write_i32::<LittleEndian>(car.race_number)?;
+    writer.write_u8(car.cup_category.into())?;
+    writer.write_u8(car.current_driver_index)?;
+    writer.write_u16::<LittleEndian>(car.nationality.into())?;
+    writer.write_u8(count(car.drivers.len(), "drivers")?)?;
+    for d in &car.drivers {
+        driver(d, writer)?;
+    }
+    Ok(())
+}
+
+fn lap<W: Write>(lap: &Lap, writer: &mut W) -> std::io::Result<()> {
+    writer.write_i32::<LittleEndian>(lap.lap_time_ms)?;
+    writer.write_u16::<LittleEndian>(lap.car_id)?;
+    writer.write_u16::<LittleEndian>(lap.driver_index)?;
+    writer.write_u8(lap.splits.len() as u8)?;
+    for split in &lap.splits {
+        writer.write_i32::<LittleEndian>(*split)?;
+    }
+    boolean(lap.is_invalid, writer)?;
+    boolean(lap.is_valid_for_best, writer)?;
+    boolean(lap.is_out_lap, writer)?;
+    boolean(lap.is_in_lap, writer)
+}
+
+fn realtime_update<W: Write>(update: &RealtimeUpdate<'_>, writer: &mut W) -> std::io::Result<()> {
+    writer.write_u8(0x02)?;
+    writer.write_u16::<LittleEndian>(update.event_index)?;
+    writer.write_u16::<LittleEndian>(update.session_index)?;
+    writer.write_u8(update.session_type.into())?;
+    writer.write_u8(update.session_phase.into())?;
+    writer.write_f32::<LittleEndian>(update.session_time)?;
+    writer.write_f32::<LittleEndian>(update.session_end_time)?;
+    writer.write_u32::<LittleEndian>(update.focused_car_index)?;
+    write_kstring(&update.active_camera_set, writer)?;
+    write_kstring(&update.active_camera, writer)?;
+    write_kstring(&update.current_hud_page, writer)?;
+    replay_info(&update.replay_info, writer)?;
+    writer.write_f32::<LittleEndian>(update.time_of_day)?;
+    writer.write_i8(update.ambient_temp)?;
+    writer.write_i8(update.track_temp)?;
+    writer.write_u8(update.clouds)?;
+    writer.write_u8(update.rain_level)?;
+    writer.write_u8(update.wetness)?;
+    lap(&update.best_session_lap, writer)
+}
+
+fn realtime_car_update<W: Write>(
+    update: &RealtimeCarUpdate,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writer.write_u8(0x03)?;
+    writer.write_u16::<LittleEndian>(update.id)?;
+    writer.write_u16::<LittleEndian>(update.driver_index)?;
+    writer.write_u8(update.driver_count)?;
+    writer.write_i8(update.gear)?;
+    writer.write_f32::<LittleEndian>(update.world_pos_x)?;
+    writer.write_f32::<LittleEndian>(update.world_pos_y)?;
+    writer.write_f32::<LittleEndian>(update.yaw)?;
+    writer.write_u8(update.car_location.into())?;
+    writer.write_u16::<LittleEndian>(update.speed_kph)?;
+    writer.write_u16::<LittleEndian>(update.position)?;
+    writer.write_u16::<LittleEndian>(update.cup_position)?;
+    writer.write_u16::<LittleEndian>(update.track_position)?;
+    writer.write_f32::<LittleEndian>(update.spline_position)?;
+    writer.write_u16::<LittleEndian>(update.laps)?;
+    writer.write_i32::<LittleEndian>(update.delta)?;
+    lap(&update.best_session_lap, writer)?;
+    lap(&update.last_lap, writer)?;
+    lap(&update.current_lap, writer)
+}
+
+fn kstring_list<W: Write>(
+    list: &[Cow<'_, str>],
+    field: &str,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writer.write_u8(count(list.len(), field)?)?;
+    for item in list {
+        write_kstring(item, writer)?;
+    }
+    Ok(())
+}
+
+fn track_data<W: Write>(data: &TrackData<'_>, writer: &mut W) -> std::io::Result<()> {
+    writer.write_u8(0x05)?;
+    writer.write_u32::<LittleEndian>(data.connection_id)?;
+    write_kstring(&data.name, writer)?;
+    writer.write_u32::<LittleEndian>(data.id)?;
+    writer.write_u32::<LittleEndian>(data.distance)?;
+    writer.write_u8(count(data.camera_sets.len(), "camera sets")?)?;
+    for (name, cameras) in &data.camera_sets {
+        write_kstring(name, writer)?;
+        kstring_list(cameras, "cameras", writer)?;
+    }
+    kstring_list(&data.hud_pages, "HUD pages", writer)
+}
+
+fn broadcasting_event<W: Write>(
+    event: &BroadcastingEvent<'_>,
+    writer: &mut W,
+) -> std::io::Result<()> {
+    writer.write_u8(0x07)?;
+    writer.write_u8(event.event_type.into())?;
+    write_kstring(&event.message, writer)?;
+    writer.write_i32::<LittleEndian>(event.time_ms)?;
+    writer.write_u32::<LittleEndian>(event.car_id as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::{
+        BroadcastingEventType, CarLocation, CarModel, CupCategory, DriverCategory, Nationality,
+        SessionPhase, SessionType,
+    };
+    use proptest::collection::{hash_map, vec};
+    use proptest::prelude::*;
+    use tinyvec::ArrayVec;
+
+    fn to_bytes(message: &InboundMessage<'_>) -> Vec<u8> {
+        let mut buf = vec![];
+        message.encode(&mut buf).unwrap();
+        buf
+    }
+
+    fn reencodes_exactly(input: &[u8]) {
+        let message = InboundMessage::decode(input).unwrap();
+        assert_eq!(to_bytes(&message), input);
+    }
+
+    #[test]
+    fn reencode_registration_result() {
+        reencodes_exactly(b"\x01\x01\x00\x00\x00\x00\x01\x10\x00Handshake failed");
+    }
+
+    #[test]
+    fn reencode_realtime_update() {
+        reencodes_exactly(include_bytes!("../../docs/pcap/realtime_update.bin"));
+    }
+
+    #[test]
+    fn reencode_realtime_car_update() {
+        reencodes_exactly(include_bytes!("../../docs/pcap/realtime_car_update.bin"));
+    }
+
+    #[test]
+    fn reencode_entrylist_update() {
+        reencodes_exactly(&[0x04, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0xe9, 0x03]);
+    }
+
+    #[test]
+    fn reencode_entrylist_car() {
+        reencodes_exactly(&[
+            0x06, 0xe9, 0x03, 0x18, 0x00, 0x00, 0x4b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x06, 0x00, 0x4d, 0x61, 0x72, 0x74, 0x69, 0x6e, 0x08, 0x00, 0x52, 0x6f, 0x77,
+            0x6e, 0x74, 0x72, 0x65, 0x65, 0x03, 0x00, 0x52, 0x4f, 0x57, 0x03, 0x05, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn reencode_broadcasting_event() {
+        reencodes_exactly(b"\x07\x05\x0d\x00Lap completed\x2c\x4a\x00\x00\xe9\x03\x00\x00");
+    }
+
+    #[test]
+    fn reencode_track_data() {
+        // Camera sets are held in a HashMap, so only the decoded contents are comparable
+        let input = include_bytes!("../../docs/pcap/track_data.bin");
+        let message = InboundMessage::decode(input).unwrap();
+        let encoded = to_bytes(&message);
+        assert_eq!(encoded.len(), input.len());
+        assert_eq!(InboundMessage::decode(&encoded).unwrap(), message);
+    }
+
+    #[test]
+    fn refuses_oversized_lists() {
+        let update = InboundMessage::EntrylistUpdate(EntrylistUpdate {
+            connection_id: 1,
+            car_ids: vec![0; u16::MAX as usize + 1],
+        });
+        assert!(update.encode(&mut vec![]).is_err());
+    }
+
+    // Any value the decoder accepts for an enum
+    fn raw_enum<T: TryFrom<u8> + Clone + std::fmt::Debug + 'static>() -> impl Strategy<Value = T> {
+        proptest::sample::select(
+            (0..=u8::MAX)
+                .filter_map(|b| T::try_from(b).ok())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn nationality() -> impl Strategy<Value = Nationality> {
+        proptest::sample::select(
+            (0..=u8::MAX as u16)
+                .filter_map(|n| Nationality::try_from(n).ok())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn text() -> impl Strategy<Value = Cow<'static, str>> {
+        ".{0,24}".prop_map(Cow::Owned)
+    }
+
+    fn float() -> impl Strategy<Value = f32> {
+        any::<f32>().prop_filter("NaN never compares equal", |f| !f.is_nan())
+    }
+
+    fn lap() -> impl Strategy<Value = Lap> {
+        (
+            any::<i32>(),
+            any::<u16>(),
+            any::<u16>(),
+            vec(any::<i32>(), 0..=3),
+            any::<[bool; 4]>(),
+        )
+            .prop_map(|(lap_time_ms, car_id, driver_index, splits, flags)| Lap {
+                lap_time_ms,
+                car_id,
+                driver_index,
+                splits: splits.into_iter().collect::<ArrayVec<[i32; 3]>>(),
+                is_invalid: flags[0],
+                is_valid_for_best: flags[1],
+                is_out_lap: flags[2],
+                is_in_lap: flags[3],
+            })
+    }
+
+    fn registration_result() -> impl Strategy<Value = InboundMessage<'static>> {
+        (any::<u32>(), any::<bool>(), any::<bool>(), text()).prop_map(
+            |(connection_id, connection_success, read_only, error_message)| {
+                InboundMessage::RegistrationResult(RegistrationResult {
+                    connection_id,
+                    connection_success,
+                    read_only,
+                    error_message,
+                })
+            },
+        )
+    }
+
+    fn realtime_update() -> impl Strategy<Value = InboundMessage<'static>> {
+        (
+            (
+                any::<u16>(),
+                any::<u16>(),
+                raw_enum::<SessionType>(),
+                raw_enum::<SessionPhase>(),
+                float(),
+                float(),
+                any::<u32>(),
+            ),
+            (text(), text(), text()),
+            proptest::option::of((float(), float(), any::<u32>())),
+            (float(), any::<i8>(), any::<i8>(), any::<[u8; 3]>(), lap()),
+        )
+            .prop_map(
+                |(
+                    (event_index, session_index, session_type, session_phase, time, end, focus),
+                    (active_camera_set, active_camera, current_hud_page),
+                    replay_info,
+                    (time_of_day, ambient_temp, track_temp, weather, best_session_lap),
+                )| {
+                    InboundMessage::RealtimeUpdate(RealtimeUpdate {
+                        event_index,
+                        session_index,
+                        session_type,
+                        session_phase,
+                        session_time: time,
+                        session_end_time: end,
+                        focused_car_index: focus,
+                        active_camera_set,
+                        active_camera,
+                        current_hud_page,
+                        replay_info: replay_info.map(
+                            |(session_time, remaining_time, focused_car_index)| ReplayInfo {
+                                session_time,
+                                remaining_time,
+                                focused_car_index,
+                            },
+                        ),
+                        time_of_day,
+                        ambient_temp,
+                        track_temp,
+                        clouds: weather[0],
+                        rain_level: weather[1],
+                        wetness: weather[2],
+                        best_session_lap,
+                    })
+                },
+            )
+    }
+
+    fn realtime_car_update() -> impl Strategy<Value = InboundMessage<'static>> {
+        (
+            (
+                any::<u16>(),
+                any::<u16>(),
+                any::<u8>(),
+                any::<i8>(),
+                float(),
+                float(),
+                float(),
+                raw_enum::<CarLocation>(),
+            ),
+            (any::<[u16; 4]>(), float(), any::<u16>(), any::<i32>()),
+            (lap(), lap(), lap()),
+        )
+            .prop_map(
+                |(
+                    (id, driver_index, driver_count, gear, world_pos_x, world_pos_y, yaw, location),
+                    (numbers, spline_position, laps, delta),
+                    (best_session_lap, last_lap, current_lap),
+                )| {
+                    InboundMessage::RealtimeCarUpdate(RealtimeCarUpdate {
+                        id,
+                        driver_index,
+                        driver_count,
+                        gear,
+                        world_pos_x,
+                        world_pos_y,
+                        yaw,
+                        car_location: location,
+                        speed_kph: numbers[0],
+                        position: numbers[1],
+                        cup_position: numbers[2],
+                        track_position: numbers[3],
+                        spline_position,
+                        laps,
+                        delta,
+                        best_session_lap,
+                        last_lap,
+                        current_lap,
+                    })
+                },
+            )
+    }
+
+    fn entrylist_update() -> impl Strategy<Value = InboundMessage<'static>> {
+        (any::<u32>(), vec(any::<u16>(), 0..40)).prop_map(|(connection_id, car_ids)| {
+            InboundMessage::EntrylistUpdate(EntrylistUpdate {
+                connection_id,
+                car_ids,
+            })
+        })
+    }
+
+    fn driver() -> impl Strategy<Value = Driver<'static>> {
+        (
+            text(),
+            text(),
+            text(),
+            raw_enum::<DriverCategory>(),
+            nationality(),
+        )
+            .prop_map(
+                |(first_name, last_name, short_name, category, nationality)| Driver {
+                    first_name,
+                    last_name,
+                    short_name,
+                    category,
+                    nationality,
+                },
+            )
+    }
+
+    fn entrylist_car() -> impl Strategy<Value = InboundMessage<'static>> {
+        (
+            any::<u16>(),
+            raw_enum::<CarModel>(),
+            text(),
+            any::<i32>(),
+            raw_enum::<CupCategory>(),
+            any::<u8>(),
+            nationality(),
+            vec(driver(), 0..4),
+        )
+            .prop_map(
+                |(
+                    id,
+                    model,
+                    team_name,
+                    race_number,
+                    cup_category,
+                    current_driver_index,
+                    nationality,
+                    drivers,
+                )| {
+                    InboundMessage::EntrylistCar(EntrylistCar {
+                        id,
+                        model,
+                        team_name,
+                        race_number,
+                        cup_category,
+                        current_driver_index,
+                        nationality,
+                        drivers,
+                    })
+                },
+            )
+    }
+
+    fn track_data() -> impl Strategy<Value = InboundMessage<'static>> {
+        (
+            any::<u32>(),
+            text(),
+            any::<u32>(),
+            any::<u32>(),
+            hash_map(text(), vec(text(), 0..6), 0..6),
+            vec(text(), 0..8),
+        )
+            .prop_map(
+                |(connection_id, name, id, distance, camera_sets, hud_pages)| {
+                    InboundMessage::TrackData(TrackData {
+                        connection_id,
+                        name,
+                        id,
+                        distance,
+                        camera_sets,
+                        hud_pages,
+                    })
+                },
+            )
+    }
+
+    fn broadcasting_event() -> impl Strategy<Value = InboundMessage<'static>> {
+        (
+            raw_enum::<BroadcastingEventType>(),
+            text(),
+            any::<i32>(),
+            any::<u16>(),
+        )
+            .prop_map(|(event_type, message, time_ms, car_id)| {
+                InboundMessage::BroadcastingEvent(BroadcastingEvent {
+                    event_type,
+                    message,
+                    time_ms,
+                    car_id,
+                })
+            })
+    }
+
+    fn inbound_message() -> impl Strategy<Value = InboundMessage<'static>> {
+        prop_oneof![
+            registration_result(),
+            realtime_update(),
+            realtime_car_update(),
+            entrylist_update(),
+            entrylist_car(),
+            track_data(),
+            broadcasting_event(),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn decode_inverts_encode(message in inbound_message()) {
+            let encoded = to_bytes(&message);
+            prop_assert_eq!(InboundMessage::decode(&encoded).unwrap(), message);
+        }
+    }
+}
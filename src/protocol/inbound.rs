@@ -51,30 +51,52 @@ use nom_supreme::error::ErrorTree;
 use nom_supreme::final_parser::ByteOffset;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
 use tinyvec::ArrayVec;
 
 use crate::protocol::acc_enum::{
     BroadcastingEventType, CarLocation, CarModel, CupCategory, DriverCategory, Nationality,
     SessionPhase, SessionType,
 };
-use crate::protocol::parser;
+use crate::protocol::{encoder, parser, DecodeMode};
+use crate::track::Track;
 
 /// An incoming message, decoded from the UDP stream sent by the simulator.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InboundMessage<'a> {
-    RegistrationResult(RegistrationResult<'a>),
-    RealtimeUpdate(RealtimeUpdate<'a>),
+    RegistrationResult(#[cfg_attr(feature = "serde", serde(borrow))] RegistrationResult<'a>),
+    RealtimeUpdate(#[cfg_attr(feature = "serde", serde(borrow))] RealtimeUpdate<'a>),
     RealtimeCarUpdate(RealtimeCarUpdate),
     EntrylistUpdate(EntrylistUpdate),
-    EntrylistCar(EntrylistCar<'a>),
-    TrackData(TrackData<'a>),
-    BroadcastingEvent(BroadcastingEvent<'a>),
+    EntrylistCar(#[cfg_attr(feature = "serde", serde(borrow))] EntrylistCar<'a>),
+    TrackData(#[cfg_attr(feature = "serde", serde(borrow))] TrackData<'a>),
+    BroadcastingEvent(#[cfg_attr(feature = "serde", serde(borrow))] BroadcastingEvent<'a>),
 }
 
 impl<'a> InboundMessage<'a> {
     /// Decode an incoming message from a UDP payload sent by the simulator.
+    ///
+    /// Decoding is strict, any unrecognised enum value causes an error.
     pub fn decode(input: &'a [u8]) -> Result<InboundMessage<'a>, ErrorTree<ByteOffset>> {
-        parser::parse(input)
+        parser::parse(input, DecodeMode::Strict)
+    }
+
+    /// Decode an incoming message, using `mode` to handle unrecognised enum values.
+    pub fn decode_with(
+        input: &'a [u8],
+        mode: DecodeMode,
+    ) -> Result<InboundMessage<'a>, ErrorTree<ByteOffset>> {
+        parser::parse(input, mode)
+    }
+
+    /// Encode the message into the packet format sent by the simulator.
+    ///
+    /// Camera sets in [`TrackData`] are written in the iteration order of their map, so an
+    /// encoded track data packet may order them differently to the one originally received.
+    pub fn encode<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        encoder::encode(self, writer)
     }
 
     /// Obtain a copy of the message with a `'static` lifetime.
@@ -101,11 +123,13 @@ impl<'a> InboundMessage<'a> {
 
 /// Describes a response to the initial broadcast client connection request.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegistrationResult<'a> {
     /// The client ID of this connection, used to notify the simulator upon disconnect.
     pub connection_id: u32,
     pub connection_success: bool,
     pub read_only: bool,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub error_message: Cow<'a, str>,
 }
 
@@ -122,7 +146,8 @@ impl<'a> RegistrationResult<'a> {
 }
 
 /// Contains the timing data for a fully or partially completed lap.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Lap {
     pub lap_time_ms: i32,
     pub car_id: u16,
@@ -136,8 +161,20 @@ pub struct Lap {
     pub is_in_lap: bool,
 }
 
+impl Lap {
+    /// The lap time, `None` for the placeholder laps sent before a lap has been completed.
+    pub fn time(&self) -> Option<Duration> {
+        if self.lap_time_ms > 0 && self.lap_time_ms != i32::MAX {
+            Some(Duration::from_millis(self.lap_time_ms as u64))
+        } else {
+            None
+        }
+    }
+}
+
 /// Contains replay playback information.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReplayInfo {
     pub session_time: f32,
     pub remaining_time: f32,
@@ -148,7 +185,8 @@ pub struct ReplayInfo {
 /// for each update.
 ///
 /// This type of update is sent approximately once per update interval.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RealtimeUpdate<'a> {
     /// The event index, starts at 0 when connecting, and increments with each new race weekend.
     pub event_index: u16,
@@ -164,10 +202,13 @@ pub struct RealtimeUpdate<'a> {
     /// Index into the entry list of the car currently focused by the simulator.
     pub focused_car_index: u32, // TODO: Implement .focused_car() on Context
     /// Active camera set, this string will be one of those returned in [`TrackData`]
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub active_camera_set: Cow<'a, str>,
     /// Active camera, this string will be one of those returned in [`TrackData`]
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub active_camera: Cow<'a, str>,
     /// Current HUD page shown, this string will be one of those returned in [`TrackData`]
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub current_hud_page: Cow<'a, str>,
     /// `None` if the current session is not a replay.
     pub replay_info: Option<ReplayInfo>,
@@ -209,7 +250,8 @@ impl<'a> RealtimeUpdate<'a> {
 /// Contains a snapshot of the state of a single car within the session.
 ///
 /// This type of update is sent approximately once per update interval.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RealtimeCarUpdate {
     /// Unique Car ID
     pub id: u16,
@@ -252,17 +294,24 @@ pub struct RealtimeCarUpdate {
 /// This packet is sent ahead of a stream of [`EntrylistCar`] packets to give the client an opportunity
 /// to pre-allocate space for the updated information. This type of packet is sent upon initial connection,
 /// when a change to the entry list occurs, or when the client explicitly requests an update.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntrylistUpdate {
+    /// The connection ID of the client this update was sent to.
+    pub connection_id: u32,
     /// The list of Car IDs in the session.
     pub car_ids: Vec<u16>,
 }
 
 /// Basic driver information.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Driver<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub first_name: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub last_name: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub short_name: Cow<'a, str>,
     pub category: DriverCategory,
     pub nationality: Nationality,
@@ -285,15 +334,18 @@ impl<'a> Driver<'a> {
 ///
 /// This packet will typically have been preceded by an [`EntrylistUpdate`] containing its ID.
 /// `nationality` and `cup_category` appear to reflect those of the current driver.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EntrylistCar<'a> {
     pub id: u16,
     pub model: CarModel,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub team_name: Cow<'a, str>,
     pub race_number: i32,
     pub cup_category: CupCategory,
     pub current_driver_index: u8,
     pub nationality: Nationality,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub drivers: Vec<Driver<'a>>,
 }
 
@@ -320,23 +372,35 @@ pub type HudPages<'a> = Vec<Cow<'a, str>>;
 
 /// Information about the current track.
 ///
-/// There is no definitive list of Track IDs, determining such a mapping is left as an exercise for the
-/// reader. `name` is typically human readable rather than the `spa_2020` format used in the config
-/// files.
-#[derive(Debug, Clone)]
+/// There is no definitive list of Track IDs, so use [`track`](TrackData::track) to identify the
+/// circuit from its name and length. `name` is typically human readable rather than the `spa_2020`
+/// format used in the config files.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackData<'a> {
+    /// The connection ID of the client this data was sent to.
+    pub connection_id: u32,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub name: Cow<'a, str>,
     pub id: u32,
     /// Distance given in meters.
     pub distance: u32,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub camera_sets: HashMap<Cow<'a, str>, CameraSet<'a>>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub hud_pages: HudPages<'a>,
 }
 
 impl<'a> TrackData<'a> {
+    /// The circuit described by this data, if it is one known to this crate.
+    pub fn track(&self) -> Option<Track> {
+        Track::identify(&self.name, self.distance)
+    }
+
     /// Obtain a copy of the track data with a `'static` lifetime.
     pub fn into_owned(self) -> TrackData<'static> {
         TrackData {
+            connection_id: self.connection_id,
             name: Cow::Owned(self.name.into_owned()),
             id: self.id,
             distance: self.distance,
@@ -360,9 +424,11 @@ impl<'a> TrackData<'a> {
 }
 
 /// A message indicating a relevant event has occurred in the session.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BroadcastingEvent<'a> {
     pub event_type: BroadcastingEventType,
+    #[cfg_attr(feature = "serde", serde(borrow))]
     pub message: Cow<'a, str>,
     pub time_ms: i32,
     /// ID of the car, for global events, `car_id` will be zero.
@@ -379,3 +445,40 @@ impl<'a> BroadcastingEvent<'a> {
         }
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::SessionType;
+
+    #[test]
+    fn serde_round_trip_borrows_strings() {
+        let input = include_bytes!("../../docs/pcap/realtime_update.bin");
+        let message = InboundMessage::decode(input).unwrap();
+
+        let json = serde_json::to_string(&message).unwrap();
+        let restored: InboundMessage<'_> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, message);
+
+        match restored {
+            InboundMessage::RealtimeUpdate(update) => {
+                assert!(matches!(update.active_camera, Cow::Borrowed("CameraPit3")));
+            }
+            _ => panic!("Expected a realtime update"),
+        }
+    }
+
+    #[test]
+    fn serde_uses_readable_representations() {
+        assert_eq!(
+            serde_json::to_string(&SessionType::Qualifying).unwrap(),
+            "\"Qualifying\""
+        );
+
+        let input = include_bytes!("../../docs/pcap/realtime_car_update.bin");
+        let message = InboundMessage::decode(input).unwrap();
+        let json = serde_json::to_value(&message).unwrap();
+        let splits = &json["RealtimeCarUpdate"]["last_lap"]["splits"];
+        assert!(splits.is_array());
+    }
+}
@@ -1,10 +1,18 @@
-use crate::protocol::PROTOCOL_VERSION;
+use crate::protocol::{parser, PROTOCOL_VERSION};
 use byteorder::{LittleEndian, WriteBytesExt};
+use nom_supreme::error::ErrorTree;
+use nom_supreme::final_parser::ByteOffset;
+use std::borrow::Cow;
 use std::io::Write;
 
-fn write_kstring<W: Write>(string: &str, writer: &mut W) -> std::io::Result<()> {
+pub(crate) fn write_kstring<W: Write>(string: &str, writer: &mut W) -> std::io::Result<()> {
     let bytes = string.as_bytes();
-    assert!(bytes.len() < u16::MAX as usize);
+    if bytes.len() > u16::MAX as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "string too long to encode",
+        ));
+    }
     writer.write_u16::<LittleEndian>(bytes.len() as u16)?;
     writer.write_all(bytes)
 }
@@ -13,13 +21,14 @@ pub trait OutboundMessage<W> {
     fn encode(self, writer: &mut W) -> std::io::Result<()>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegistrationRequest<'a> {
-    version: u8,
-    username: &'a str,
-    password: &'a str,
+    pub(crate) version: u8,
+    username: Cow<'a, str>,
+    password: Cow<'a, str>,
     interval: u32,
-    command_password: &'a str,
+    command_password: Cow<'a, str>,
 }
 
 impl<'a> RegistrationRequest<'a> {
@@ -31,12 +40,39 @@ impl<'a> RegistrationRequest<'a> {
     ) -> Self {
         Self {
             version: PROTOCOL_VERSION,
-            username,
-            password,
+            username: username.into(),
+            password: password.into(),
             interval,
-            command_password,
+            command_password: command_password.into(),
         }
     }
+
+    /// Whether this request asks for access to broadcast commands as well as the data feed.
+    pub(crate) fn requests_commands(&self) -> bool {
+        !self.command_password.is_empty()
+    }
+
+    /// Protocol version the client expects.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    /// Requested update interval in milliseconds.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn command_password(&self) -> &str {
+        &self.command_password
+    }
 }
 
 impl<W: Write> OutboundMessage<W> for RegistrationRequest<'_> {
@@ -50,7 +86,8 @@ impl<W: Write> OutboundMessage<W> for RegistrationRequest<'_> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnregisterRequest {
     connection_id: u32,
 }
@@ -59,6 +96,10 @@ impl UnregisterRequest {
     pub fn new(connection_id: u32) -> Self {
         Self { connection_id }
     }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
 }
 
 impl<W: Write> OutboundMessage<W> for UnregisterRequest {
@@ -68,10 +109,384 @@ impl<W: Write> OutboundMessage<W> for UnregisterRequest {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntrylistRequest {
+    connection_id: u32,
+}
+
+impl EntrylistRequest {
+    pub fn new(connection_id: u32) -> Self {
+        Self { connection_id }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for EntrylistRequest {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x0a])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackDataRequest {
+    connection_id: u32,
+}
+
+impl TrackDataRequest {
+    pub fn new(connection_id: u32) -> Self {
+        Self { connection_id }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for TrackDataRequest {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x0b])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)
+    }
+}
+
+/// Requests the simulator switch to a different HUD page.
+///
+/// `hud_page` should be one of the pages listed in [`TrackData`](crate::protocol::inbound::TrackData).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HudPageRequest<'a> {
+    connection_id: u32,
+    hud_page: Cow<'a, str>,
+}
+
+impl<'a> HudPageRequest<'a> {
+    pub fn new(connection_id: u32, hud_page: &'a str) -> Self {
+        Self {
+            connection_id,
+            hud_page: hud_page.into(),
+        }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    pub fn hud_page(&self) -> &str {
+        &self.hud_page
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for HudPageRequest<'_> {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x31])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)?;
+        write_kstring(&self.hud_page, writer)
+    }
+}
+
+/// Requests the simulator change the focused car, the active camera, or both.
+///
+/// Either part of the request may be left as `None`, in which case the simulator keeps its current
+/// selection. Camera sets and cameras should be taken from [`TrackData`](crate::protocol::inbound::TrackData).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FocusRequest<'a> {
+    connection_id: u32,
+    car_index: Option<u16>,
+    camera: Option<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> FocusRequest<'a> {
+    pub fn new(
+        connection_id: u32,
+        car_index: Option<u16>,
+        camera: Option<(&'a str, &'a str)>,
+    ) -> Self {
+        Self {
+            connection_id,
+            car_index,
+            camera: camera.map(|(set, camera)| (set.into(), camera.into())),
+        }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    pub fn car_index(&self) -> Option<u16> {
+        self.car_index
+    }
+
+    /// The requested camera set and camera.
+    pub fn camera(&self) -> Option<(&str, &str)> {
+        self.camera
+            .as_ref()
+            .map(|(set, camera)| (&**set, &**camera))
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for FocusRequest<'_> {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x32])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)?;
+        // Each part of the request is preceded by a presence flag
+        match self.car_index {
+            Some(car_index) => {
+                writer.write_u8(1)?;
+                writer.write_u16::<LittleEndian>(car_index)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+        match self.camera {
+            Some((camera_set, camera)) => {
+                writer.write_u8(1)?;
+                write_kstring(&camera_set, writer)?;
+                write_kstring(&camera, writer)
+            }
+            None => writer.write_u8(0),
+        }
+    }
+}
+
+/// Requests an instant replay of a section of the current session.
+///
+/// `start_session_time` and `duration_ms` are both given in milliseconds. If no initial car or camera
+/// is supplied the simulator keeps the current focus.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstantReplayRequest<'a> {
+    connection_id: u32,
+    start_session_time: f32,
+    duration_ms: f32,
+    initial_car_index: Option<u16>,
+    initial_camera: Option<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> InstantReplayRequest<'a> {
+    pub fn new(
+        connection_id: u32,
+        start_session_time: f32,
+        duration_ms: f32,
+        initial_car_index: Option<u16>,
+        initial_camera: Option<(&'a str, &'a str)>,
+    ) -> Self {
+        Self {
+            connection_id,
+            start_session_time,
+            duration_ms,
+            initial_car_index,
+            initial_camera: initial_camera.map(|(set, camera)| (set.into(), camera.into())),
+        }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    pub fn start_session_time(&self) -> f32 {
+        self.start_session_time
+    }
+
+    pub fn duration_ms(&self) -> f32 {
+        self.duration_ms
+    }
+
+    pub fn initial_car_index(&self) -> Option<u16> {
+        self.initial_car_index
+    }
+
+    pub fn initial_camera(&self) -> Option<(&str, &str)> {
+        self.initial_camera
+            .as_ref()
+            .map(|(set, camera)| (&**set, &**camera))
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for InstantReplayRequest<'_> {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x33])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)?;
+        writer.write_f32::<LittleEndian>(self.start_session_time)?;
+        writer.write_f32::<LittleEndian>(self.duration_ms)?;
+        // The reference client sends -1 and empty strings to leave the focus unchanged
+        writer.write_i32::<LittleEndian>(self.initial_car_index.map_or(-1, i32::from))?;
+        let (camera_set, camera) = self.initial_camera().unwrap_or(("", ""));
+        write_kstring(camera_set, writer)?;
+        write_kstring(camera, writer)
+    }
+}
+
+/// Requests playback of the manual replay highlight.
+///
+/// The reference client reserves this message type but does not yet send it, so only the packet
+/// type and connection ID are encoded.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayManualReplayHighlightRequest {
+    connection_id: u32,
+}
+
+impl PlayManualReplayHighlightRequest {
+    pub fn new(connection_id: u32) -> Self {
+        Self { connection_id }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for PlayManualReplayHighlightRequest {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x34])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)
+    }
+}
+
+/// Requests the simulator save the manual replay highlight.
+///
+/// As with [`PlayManualReplayHighlightRequest`], only the packet type and connection ID are encoded.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SaveManualReplayHighlightRequest {
+    connection_id: u32,
+}
+
+impl SaveManualReplayHighlightRequest {
+    pub fn new(connection_id: u32) -> Self {
+        Self { connection_id }
+    }
+
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for SaveManualReplayHighlightRequest {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&[0x3c])?; // Packet type
+        writer.write_u32::<LittleEndian>(self.connection_id)
+    }
+}
+
+/// A message sent by a broadcasting client, decoded from a UDP payload.
+///
+/// This is the counterpart to [`InboundMessage`](crate::protocol::inbound::InboundMessage) for
+/// traffic travelling towards the simulator, and is mostly useful for relays and for inspecting
+/// captures of other broadcasting tools.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClientMessage<'a> {
+    RegistrationRequest(RegistrationRequest<'a>),
+    UnregisterRequest(UnregisterRequest),
+    EntrylistRequest(EntrylistRequest),
+    TrackDataRequest(TrackDataRequest),
+    HudPageRequest(HudPageRequest<'a>),
+    FocusRequest(FocusRequest<'a>),
+    InstantReplayRequest(InstantReplayRequest<'a>),
+    PlayManualReplayHighlightRequest(PlayManualReplayHighlightRequest),
+    SaveManualReplayHighlightRequest(SaveManualReplayHighlightRequest),
+}
+
+impl<'a> ClientMessage<'a> {
+    /// Decode a message from a UDP payload sent by a broadcasting client.
+    pub fn decode(input: &'a [u8]) -> Result<ClientMessage<'a>, ErrorTree<ByteOffset>> {
+        parser::parse_client(input)
+    }
+
+    /// The connection ID the message was sent on, `None` for registration requests which are sent
+    /// before an ID is assigned.
+    pub fn connection_id(&self) -> Option<u32> {
+        match self {
+            ClientMessage::RegistrationRequest(_) => None,
+            ClientMessage::UnregisterRequest(req) => Some(req.connection_id),
+            ClientMessage::EntrylistRequest(req) => Some(req.connection_id),
+            ClientMessage::TrackDataRequest(req) => Some(req.connection_id),
+            ClientMessage::HudPageRequest(req) => Some(req.connection_id),
+            ClientMessage::FocusRequest(req) => Some(req.connection_id),
+            ClientMessage::InstantReplayRequest(req) => Some(req.connection_id),
+            ClientMessage::PlayManualReplayHighlightRequest(req) => Some(req.connection_id),
+            ClientMessage::SaveManualReplayHighlightRequest(req) => Some(req.connection_id),
+        }
+    }
+}
+
+impl<W: Write> OutboundMessage<W> for ClientMessage<'_> {
+    fn encode(self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            ClientMessage::RegistrationRequest(req) => req.encode(writer),
+            ClientMessage::UnregisterRequest(req) => req.encode(writer),
+            ClientMessage::EntrylistRequest(req) => req.encode(writer),
+            ClientMessage::TrackDataRequest(req) => req.encode(writer),
+            ClientMessage::HudPageRequest(req) => req.encode(writer),
+            ClientMessage::FocusRequest(req) => req.encode(writer),
+            ClientMessage::InstantReplayRequest(req) => req.encode(writer),
+            ClientMessage::PlayManualReplayHighlightRequest(req) => req.encode(writer),
+            ClientMessage::SaveManualReplayHighlightRequest(req) => req.encode(writer),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn client_message_round_trip() {
+        let mut buf = vec![];
+        FocusRequest::new(7, Some(1001), Some(("set1", "CameraPit3")))
+            .encode(&mut buf)
+            .unwrap();
+        let message = ClientMessage::decode(&buf).unwrap();
+        assert_eq!(message.connection_id(), Some(7));
+
+        let mut reencoded = vec![];
+        message.encode(&mut reencoded).unwrap();
+        assert_eq!(reencoded, buf);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_accepts_escaped_and_owned_strings() {
+        let messages = vec![
+            ClientMessage::RegistrationRequest(RegistrationRequest::new(
+                "Race \"Control\"",
+                "asd",
+                250,
+                "",
+            )),
+            ClientMessage::HudPageRequest(HudPageRequest::new(1, "Basic\tHUD")),
+            ClientMessage::FocusRequest(FocusRequest::new(1, None, Some(("set\\1", "Cam")))),
+            ClientMessage::InstantReplayRequest(InstantReplayRequest::new(
+                1,
+                0.0,
+                1000.0,
+                None,
+                Some(("set\n1", "Cam")),
+            )),
+        ];
+
+        let json = serde_json::to_string(&messages).unwrap();
+        let restored: Vec<ClientMessage<'_>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, messages);
+        let read: Vec<ClientMessage<'static>> =
+            serde_json::from_reader(std::io::Cursor::new(json.as_bytes())).unwrap();
+        assert_eq!(read, messages);
+
+        match &restored[0] {
+            ClientMessage::RegistrationRequest(req) => {
+                assert_eq!(req.username(), "Race \"Control\"");
+            }
+            _ => panic!("Expected a registration request"),
+        }
+    }
+
     #[test]
     fn encode_registration_request() {
         let req = RegistrationRequest::new("Your name", "asd", 250, "");
@@ -85,4 +500,63 @@ mod tests {
 
         assert_eq!(&buf, &expected);
     }
+
+    #[test]
+    fn encode_entrylist_request() {
+        let mut buf = vec![];
+        EntrylistRequest::new(1)
+            .encode(&mut buf)
+            .expect("Failed to encode");
+
+        assert_eq!(&buf, &[0x0a, 0x01, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn encode_hud_page_request() {
+        let mut buf = vec![];
+        HudPageRequest::new(1, "Basic HUD")
+            .encode(&mut buf)
+            .expect("Failed to encode");
+
+        assert_eq!(&buf[..7], &[0x31, 0x01, 0x00, 0x00, 0x00, 0x09, 0x00]);
+        assert_eq!(&buf[7..], b"Basic HUD");
+    }
+
+    #[test]
+    fn encode_focus_request() {
+        let mut buf = vec![];
+        FocusRequest::new(1, Some(1001), None)
+            .encode(&mut buf)
+            .expect("Failed to encode");
+
+        assert_eq!(
+            &buf,
+            &[0x32, 0x01, 0x00, 0x00, 0x00, 0x01, 0xe9, 0x03, 0x00]
+        );
+
+        let mut buf = vec![];
+        FocusRequest::new(1, None, Some(("set1", "CameraPit3")))
+            .encode(&mut buf)
+            .expect("Failed to encode");
+
+        assert_eq!(&buf[..8], &[0x32, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x04]);
+        assert_eq!(&buf[9..13], b"set1");
+        assert_eq!(&buf[13..15], &[0x0a, 0x00]);
+        assert_eq!(&buf[15..], b"CameraPit3");
+    }
+
+    #[test]
+    fn encode_instant_replay_request() {
+        let mut buf = vec![];
+        InstantReplayRequest::new(1, 60_000.0, 10_000.0, None, None)
+            .encode(&mut buf)
+            .expect("Failed to encode");
+
+        let expected = &[
+            0x33, 0x01, 0x00, 0x00, 0x00, 0x00, 0x60, 0x6a, 0x47, 0x00, 0x40, 0x1c, 0x46, 0xff,
+            0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        assert_eq!(&buf, &expected);
+    }
 }
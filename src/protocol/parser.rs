@@ -1,10 +1,10 @@
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_while};
-use nom::combinator::{map, map_res, not};
+use nom::combinator::{map, map_res};
 use nom::error::context;
 use nom::multi::{fold_many0, length_count, length_value};
 use nom::number::complete::{le_f32, le_i32, le_i8, le_u16, le_u32, le_u8};
-use nom::sequence::tuple;
+use nom::sequence::{preceded, tuple};
 use nom::IResult;
 use nom_supreme::error::ErrorTree;
 use nom_supreme::final_parser::{final_parser, ByteOffset};
@@ -20,10 +20,19 @@ use crate::protocol::inbound::{
     BroadcastingEvent, CameraSet, Driver, EntrylistCar, EntrylistUpdate, InboundMessage, Lap,
     RealtimeCarUpdate, RealtimeUpdate, RegistrationResult, ReplayInfo, TrackData,
 };
+use crate::protocol::outbound::{
+    ClientMessage, EntrylistRequest, FocusRequest, HudPageRequest, InstantReplayRequest,
+    PlayManualReplayHighlightRequest, RegistrationRequest, SaveManualReplayHighlightRequest,
+    TrackDataRequest, UnregisterRequest,
+};
+use crate::protocol::{DecodeError, DecodeMode};
 
 type Res<T, U> = IResult<T, U, ErrorTree<T>>;
 
-pub(crate) fn parse(input: &[u8]) -> Result<InboundMessage, ErrorTree<ByteOffset>> {
+pub(crate) fn parse(
+    input: &[u8],
+    mode: DecodeMode,
+) -> Result<InboundMessage<'_>, ErrorTree<ByteOffset>> {
     final_parser(context(
         "incoming_message",
         alt((
@@ -31,14 +40,48 @@ pub(crate) fn parse(input: &[u8]) -> Result<InboundMessage, ErrorTree<ByteOffset
             map(realtime_update, InboundMessage::RealtimeUpdate),
             map(realtime_car_update, InboundMessage::RealtimeCarUpdate),
             map(entrylist_update, InboundMessage::EntrylistUpdate),
-            map(entrylist_car, InboundMessage::EntrylistCar),
+            map(|i| entrylist_car(i, mode), InboundMessage::EntrylistCar),
             map(track_data, InboundMessage::TrackData),
-            map(broadcasting_event, InboundMessage::BroadcastingEvent),
+            map(
+                |i| broadcasting_event(i, mode),
+                InboundMessage::BroadcastingEvent,
+            ),
+        )),
+    ))(input)
+}
+
+pub(crate) fn parse_client(input: &[u8]) -> Result<ClientMessage<'_>, ErrorTree<ByteOffset>> {
+    final_parser(context(
+        "client_message",
+        alt((
+            map(registration_request, ClientMessage::RegistrationRequest),
+            map(connection_request(0x09), |id| {
+                ClientMessage::UnregisterRequest(UnregisterRequest::new(id))
+            }),
+            map(connection_request(0x0a), |id| {
+                ClientMessage::EntrylistRequest(EntrylistRequest::new(id))
+            }),
+            map(connection_request(0x0b), |id| {
+                ClientMessage::TrackDataRequest(TrackDataRequest::new(id))
+            }),
+            map(hud_page_request, ClientMessage::HudPageRequest),
+            map(focus_request, ClientMessage::FocusRequest),
+            map(instant_replay_request, ClientMessage::InstantReplayRequest),
+            map(connection_request(0x34), |id| {
+                ClientMessage::PlayManualReplayHighlightRequest(
+                    PlayManualReplayHighlightRequest::new(id),
+                )
+            }),
+            map(connection_request(0x3c), |id| {
+                ClientMessage::SaveManualReplayHighlightRequest(
+                    SaveManualReplayHighlightRequest::new(id),
+                )
+            }),
         )),
     ))(input)
 }
 
-fn registration_result(input: &[u8]) -> Res<&[u8], RegistrationResult> {
+fn registration_result(input: &[u8]) -> Res<&[u8], RegistrationResult<'_>> {
     context(
         "registration_result",
         tuple((tag(&[0x01]), le_u32, boolean, boolean, kstring)),
@@ -56,6 +99,92 @@ fn registration_result(input: &[u8]) -> Res<&[u8], RegistrationResult> {
     })
 }
 
+fn registration_request(input: &[u8]) -> Res<&[u8], RegistrationRequest<'_>> {
+    context(
+        "registration_request",
+        tuple((tag(&[0x01]), le_u8, kstring, kstring, le_u32, kstring)),
+    )(input)
+    .map(
+        |(next_input, (_, version, username, password, interval, command_password))| {
+            let mut request =
+                RegistrationRequest::new(username, password, interval, command_password);
+            request.version = version;
+            (next_input, request)
+        },
+    )
+}
+
+// Most requests consist of only the packet type and the client's connection ID
+fn connection_request(packet_type: u8) -> impl Fn(&[u8]) -> Res<&[u8], u32> {
+    move |input| context("connection_request", preceded(tag([packet_type]), le_u32))(input)
+}
+
+fn hud_page_request(input: &[u8]) -> Res<&[u8], HudPageRequest<'_>> {
+    context("hud_page_request", tuple((tag(&[0x31]), le_u32, kstring)))(input).map(
+        |(next_input, (_, connection_id, hud_page))| {
+            (next_input, HudPageRequest::new(connection_id, hud_page))
+        },
+    )
+}
+
+// Optional parts of a focus request are preceded by a presence flag
+fn camera_selection(input: &[u8]) -> Res<&[u8], Option<(&str, &str)>> {
+    context(
+        "camera_selection",
+        alt((
+            map(tag(&[0x00]), |_| None),
+            map(preceded(le_u8, tuple((kstring, kstring))), Some),
+        )),
+    )(input)
+}
+
+fn focus_request(input: &[u8]) -> Res<&[u8], FocusRequest<'_>> {
+    context(
+        "focus_request",
+        tuple((
+            tag(&[0x32]),
+            le_u32,
+            alt((
+                map(tag(&[0x00]), |_| None),
+                map(preceded(le_u8, le_u16), Some),
+            )),
+            camera_selection,
+        )),
+    )(input)
+    .map(|(next_input, (_, connection_id, car_index, camera))| {
+        (
+            next_input,
+            FocusRequest::new(connection_id, car_index, camera),
+        )
+    })
+}
+
+fn instant_replay_request(input: &[u8]) -> Res<&[u8], InstantReplayRequest<'_>> {
+    context(
+        "instant_replay_request",
+        tuple((
+            tag(&[0x33]),
+            le_u32,
+            le_f32,
+            le_f32,
+            // -1 and a pair of empty strings leave the focus unchanged
+            map(le_i32, |i| u16::try_from(i).ok()),
+            map(tuple((kstring, kstring)), |camera| match camera {
+                ("", "") => None,
+                camera => Some(camera),
+            }),
+        )),
+    )(input)
+    .map(
+        |(next_input, (_, connection_id, start, duration, car_index, camera))| {
+            (
+                next_input,
+                InstantReplayRequest::new(connection_id, start, duration, car_index, camera),
+            )
+        },
+    )
+}
+
 // Parse a 'Kunos' string, which is an int16 length marker followed by N bytes of UTF-8 string data
 fn kstring(input: &[u8]) -> Res<&[u8], &str> {
     context(
@@ -67,6 +196,20 @@ fn kstring(input: &[u8]) -> Res<&[u8], &str> {
     )(input)
 }
 
+// Enum fields fail to parse on unrecognised values in strict mode, lenient mode keeps them as
+// the enum's `Unknown` variant instead
+fn enum_value<'a, R, T>(
+    mode: DecodeMode,
+    raw: fn(&'a [u8]) -> Res<&'a [u8], R>,
+    strict: fn(R) -> Result<T, DecodeError>,
+    lenient: fn(R) -> T,
+) -> impl FnMut(&'a [u8]) -> Res<&'a [u8], T> {
+    move |input| match mode {
+        DecodeMode::Strict => map_res(raw, strict)(input),
+        DecodeMode::Lenient => map(raw, lenient)(input),
+    }
+}
+
 fn boolean(input: &[u8]) -> Res<&[u8], bool> {
     context("boolean", map(le_u8, |i: u8| i != 0))(input)
 }
@@ -82,17 +225,26 @@ fn entrylist_update(input: &[u8]) -> Res<&[u8], EntrylistUpdate> {
             length_count(le_u16, le_u16), // List of car IDs
         )),
     )(input)
-    .map(|(next_input, res)| (next_input, EntrylistUpdate { car_ids: res.2 }))
+    .map(|(next_input, res)| {
+        (
+            next_input,
+            EntrylistUpdate {
+                connection_id: res.1,
+                car_ids: res.2,
+            },
+        )
+    })
 }
 
-// Replay info is not included in the datagram if it's not currently a replay context
+// Replay info is preceded by a flag byte, and is not included in the datagram if it's not
+// currently a replay context
 fn replay_info(input: &[u8]) -> Res<&[u8], Option<ReplayInfo>> {
     context(
         "replay_info",
         alt((
             map(tag(&[0x00]), |_| None),
             map(
-                tuple((not(tag(&[0x00])), le_f32, le_f32, le_u32)),
+                tuple((le_u8, le_f32, le_f32, le_u32)),
                 |(_, session_time, remaining_time, focused_car_index)| {
                     Some(ReplayInfo {
                         session_time,
@@ -106,15 +258,20 @@ fn replay_info(input: &[u8]) -> Res<&[u8], Option<ReplayInfo>> {
 }
 
 // Parse the driver information supplied in the middle of EntrylistCar packets
-fn driver(input: &[u8]) -> Res<&[u8], Driver> {
+fn driver(input: &[u8], mode: DecodeMode) -> Res<&[u8], Driver<'_>> {
     context(
         "driver",
         tuple((
             kstring,
             kstring,
             kstring,
-            map_res(le_u8, DriverCategory::try_from),
-            map_res(le_u16, Nationality::try_from),
+            enum_value(
+                mode,
+                le_u8,
+                DriverCategory::try_from,
+                DriverCategory::from_raw,
+            ),
+            enum_value(mode, le_u16, Nationality::try_from, Nationality::from_raw),
         )),
     )(input)
     .map(
@@ -133,19 +290,19 @@ fn driver(input: &[u8]) -> Res<&[u8], Driver> {
     )
 }
 
-fn entrylist_car(input: &[u8]) -> Res<&[u8], EntrylistCar> {
+fn entrylist_car(input: &[u8], mode: DecodeMode) -> Res<&[u8], EntrylistCar<'_>> {
     context(
         "entrylist_car",
         tuple((
             tag(&[0x06]),
             le_u16,
-            map_res(le_u8, CarModel::try_from),
+            enum_value(mode, le_u8, CarModel::try_from, CarModel::from_raw),
             kstring,
             le_i32,
-            map_res(le_u8, CupCategory::try_from),
+            enum_value(mode, le_u8, CupCategory::try_from, CupCategory::from_raw),
             le_u8,
-            map_res(le_u16, Nationality::try_from),
-            length_count(le_u8, driver),
+            enum_value(mode, le_u16, Nationality::try_from, Nationality::from_raw),
+            length_count(le_u8, |i| driver(i, mode)),
         )),
     )(input)
     .map(
@@ -222,7 +379,7 @@ fn lap(input: &[u8]) -> Res<&[u8], Lap> {
     })
 }
 
-fn realtime_update(input: &[u8]) -> Res<&[u8], RealtimeUpdate> {
+fn realtime_update(input: &[u8]) -> Res<&[u8], RealtimeUpdate<'_>> {
     context(
         "realtime_update",
         tuple((
@@ -376,40 +533,41 @@ fn realtime_car_update(input: &[u8]) -> Res<&[u8], RealtimeCarUpdate> {
     )
 }
 
-fn camera_set<'a>(input: &'a [u8]) -> Res<&'a [u8], (Cow<'a, str>, CameraSet)> {
+fn camera_set<'a>(input: &'a [u8]) -> Res<&'a [u8], (Cow<'a, str>, CameraSet<'a>)> {
     context("camera_set", tuple((kstring, length_count(le_u8, kstring))))(input).map(
         |(next_input, (set_name, cameras))| {
             (
                 next_input,
                 (
                     Cow::Borrowed(set_name),
-                    cameras.into_iter().map(|s| Cow::Borrowed(s)).collect(),
+                    cameras.into_iter().map(Cow::Borrowed).collect(),
                 ),
             )
         },
     )
 }
 
-fn track_data(input: &[u8]) -> Res<&[u8], TrackData> {
+fn track_data(input: &[u8]) -> Res<&[u8], TrackData<'_>> {
     context(
         "track_data",
         tuple((
             tag([0x05]),
-            le_i32,
+            le_u32,
             kstring,
             le_u32,
             le_u32,
             length_count(le_u8, camera_set),
             map(length_count(le_u8, kstring), |h| {
-                h.into_iter().map(|s| Cow::Borrowed(s)).collect()
+                h.into_iter().map(Cow::Borrowed).collect()
             }),
         )),
     )(input)
     .map(
-        |(next_input, (_, _, name, id, distance, camera_sets, hud_pages))| {
+        |(next_input, (_, connection_id, name, id, distance, camera_sets, hud_pages))| {
             (
                 next_input,
                 TrackData {
+                    connection_id,
                     name: Cow::Borrowed(name),
                     id,
                     distance,
@@ -421,12 +579,17 @@ fn track_data(input: &[u8]) -> Res<&[u8], TrackData> {
     )
 }
 
-fn broadcasting_event(input: &[u8]) -> Res<&[u8], BroadcastingEvent> {
+fn broadcasting_event(input: &[u8], mode: DecodeMode) -> Res<&[u8], BroadcastingEvent<'_>> {
     context(
         "broadcasting_event",
         tuple((
             tag([0x07]),
-            map_res(le_u8, BroadcastingEventType::try_from),
+            enum_value(
+                mode,
+                le_u8,
+                BroadcastingEventType::try_from,
+                BroadcastingEventType::from_raw,
+            ),
             kstring,
             le_i32,
             map(le_u32, |i| {
@@ -547,7 +710,7 @@ mod tests {
             0x01, 0x06, 0x00, 0x4d, 0x61, 0x72, 0x74, 0x69, 0x6e, 0x08, 0x00, 0x52, 0x6f, 0x77,
             0x6e, 0x74, 0x72, 0x65, 0x65, 0x03, 0x00, 0x52, 0x4f, 0x57, 0x03, 0x05, 0x00,
         ];
-        let res = entrylist_car(input).unwrap().1;
+        let res = entrylist_car(input, DecodeMode::Strict).unwrap().1;
 
         assert_eq!(res.id, 1001);
         assert_eq!(res.race_number, 75);
@@ -559,6 +722,22 @@ mod tests {
         assert_eq!(res.model, CarModel::Ferrari488Evo);
     }
 
+    #[test]
+    fn parse_unknown_enum_values() {
+        // As parse_entrylist_car, but with car model 254 and nationality 300
+        let input: &[u8] = &[
+            0x06, 0xe9, 0x03, 0xfe, 0x00, 0x00, 0x4b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x06, 0x00, 0x4d, 0x61, 0x72, 0x74, 0x69, 0x6e, 0x08, 0x00, 0x52, 0x6f, 0x77,
+            0x6e, 0x74, 0x72, 0x65, 0x65, 0x03, 0x00, 0x52, 0x4f, 0x57, 0x03, 0x2c, 0x01,
+        ];
+        assert!(parse(input, DecodeMode::Strict).is_err());
+
+        let res = entrylist_car(input, DecodeMode::Lenient).unwrap().1;
+        assert_eq!(res.model, CarModel::Unknown(254));
+        assert_eq!(res.drivers[0].nationality, Nationality::Unknown(300));
+        assert_eq!(res.drivers[0].category, DriverCategory::Platinum);
+    }
+
     #[test]
     fn parse_track_data() {
         let input = include_bytes!("../../docs/pcap/track_data.bin");
@@ -575,7 +754,7 @@ mod tests {
     fn parse_broadcasting_event() {
         // Handwritten as we don't yet have a pcap of a Broadcast Event
         let input = b"\x07\x05\x0d\x00Lap completed\x2c\x4a\x00\x00\xe9\x03\x00\x00";
-        let res = broadcasting_event(input).unwrap().1;
+        let res = broadcasting_event(input, DecodeMode::Strict).unwrap().1;
 
         assert_eq!(res.car_id, 1001);
         assert_eq!(res.message, "Lap completed");
@@ -590,4 +769,66 @@ mod tests {
         let res = InboundMessage::decode(input);
         assert!(res.is_err());
     }
+
+    #[test]
+    fn parse_registration_request() {
+        let input = &[
+            0x01, 0x04, 0x09, 0x00, 0x59, 0x6f, 0x75, 0x72, 0x20, 0x6e, 0x61, 0x6d, 0x65, 0x03,
+            0x00, 0x61, 0x73, 0x64, 0xfa, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let res = registration_request(input).unwrap().1;
+
+        assert_eq!(res.version(), 4);
+        assert_eq!(res.username(), "Your name");
+        assert_eq!(res.password(), "asd");
+        assert_eq!(res.interval(), 250);
+        assert_eq!(res.command_password(), "");
+    }
+
+    #[test]
+    fn parse_connection_requests() {
+        let res = parse_client(&[0x09, 0x01, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(
+            res,
+            ClientMessage::UnregisterRequest(UnregisterRequest::new(1))
+        );
+
+        let res = parse_client(&[0x0b, 0x02, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(
+            res,
+            ClientMessage::TrackDataRequest(TrackDataRequest::new(2))
+        );
+
+        assert!(parse_client(&[0x0a, 0x01, 0x00]).is_err());
+    }
+
+    #[test]
+    fn parse_focus_request() {
+        let input = b"\x32\x01\x00\x00\x00\x00\x01\x04\x00set1\x0a\x00CameraPit3";
+        let res = focus_request(input).unwrap().1;
+
+        assert_eq!(res.connection_id(), 1);
+        assert_eq!(res.car_index(), None);
+        assert_eq!(res.camera(), Some(("set1", "CameraPit3")));
+    }
+
+    #[test]
+    fn parse_instant_replay_request() {
+        let input = &[
+            0x33, 0x01, 0x00, 0x00, 0x00, 0x00, 0x60, 0x6a, 0x47, 0x00, 0x40, 0x1c, 0x46, 0xff,
+            0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let res = instant_replay_request(input).unwrap().1;
+
+        assert_eq!(res.start_session_time(), 60_000.0);
+        assert_eq!(res.duration_ms(), 10_000.0);
+        assert_eq!(res.initial_car_index(), None);
+        assert_eq!(res.initial_camera(), None);
+    }
+
+    #[test]
+    fn inbound_packets_are_not_client_messages() {
+        let input = include_bytes!("../../docs/pcap/realtime_update.bin");
+        assert!(parse_client(input).is_err());
+    }
 }
@@ -0,0 +1,307 @@
+//! Recording and playback of Broadcasting API sessions.
+//!
+//! A [`Recorder`] writes raw datagrams received from the simulator to a capture file, and a
+//! [`Player`] reads them back. Passing a `Player` to
+//! [`BroadcastingClient::replay`](crate::client::BroadcastingClient::replay) feeds the recorded
+//! datagrams through the same decoding, [`Context`](crate::session::Context) bookkeeping and
+//! [`MessageHandler`](crate::client::MessageHandler) dispatch as a live session.
+//!
+//! # File format
+//!
+//! Captures start with the 4 byte magic `ACBC` and a `u8` format version, followed by one record
+//! per datagram. Each record is a little-endian `u64` timestamp in microseconds since the start of
+//! the recording, a little-endian `u16` payload length, and the payload itself.
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"ACBC";
+const FORMAT_VERSION: u8 = 1;
+
+/// A single datagram read from a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Datagram {
+    /// Time since the start of the recording.
+    pub timestamp: Duration,
+    pub payload: Vec<u8>,
+}
+
+/// Writes timestamped datagrams to a capture.
+pub struct Recorder<W: Write> {
+    writer: W,
+    started: Instant,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Creates a new capture file at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Recorder::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Writes the capture header to `writer`, timestamps are measured from this call.
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(FORMAT_VERSION)?;
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+        })
+    }
+
+    /// Records a datagram received now.
+    pub fn record(&mut self, datagram: &[u8]) -> std::io::Result<()> {
+        let timestamp = self.started.elapsed();
+        self.record_at(timestamp, datagram)
+    }
+
+    /// Records a datagram with an explicit timestamp, for converting captures from other sources.
+    pub fn record_at(&mut self, timestamp: Duration, datagram: &[u8]) -> std::io::Result<()> {
+        if datagram.len() > u16::MAX as usize {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "datagram too large to record",
+            ));
+        }
+        self.writer
+            .write_u64::<LittleEndian>(timestamp.as_micros() as u64)?;
+        self.writer
+            .write_u16::<LittleEndian>(datagram.len() as u16)?;
+        self.writer.write_all(datagram)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    pub(crate) fn into_boxed(self) -> Recorder<Box<dyn Write + Send>>
+    where
+        W: Send + 'static,
+    {
+        Recorder {
+            writer: Box::new(self.writer),
+            started: self.started,
+        }
+    }
+}
+
+/// How quickly a [`Player`] hands out datagrams.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlaybackSpeed {
+    /// Datagrams are spaced as they were when recorded.
+    RealTime,
+    /// Playback runs faster (or slower, for factors below 1.0) than real time. The factor must be
+    /// finite and greater than zero.
+    Accelerated(f64),
+    /// Each datagram is returned immediately, so the caller controls the pace.
+    Stepwise,
+}
+
+impl PlaybackSpeed {
+    fn validate(self) -> std::io::Result<Self> {
+        match self {
+            PlaybackSpeed::Accelerated(factor) if !(factor.is_finite() && factor > 0.0) => {
+                Err(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("invalid playback speed factor {}", factor),
+                ))
+            }
+            speed => Ok(speed),
+        }
+    }
+}
+
+/// Reads datagrams back from a capture written by a [`Recorder`].
+pub struct Player<R: Read> {
+    reader: R,
+    speed: PlaybackSpeed,
+    // Wall clock time and capture timestamp that playback pacing is measured from
+    origin: Option<(Instant, Duration)>,
+}
+
+impl Player<BufReader<File>> {
+    /// Opens the capture file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P, speed: PlaybackSpeed) -> std::io::Result<Self> {
+        Player::new(BufReader::new(File::open(path)?), speed)
+    }
+}
+
+impl<R: Read> Player<R> {
+    /// Reads and checks the capture header from `reader`.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if `speed` has an invalid factor.
+    pub fn new(mut reader: R, speed: PlaybackSpeed) -> std::io::Result<Self> {
+        let speed = speed.validate()?;
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "not an acbc capture file",
+            ));
+        }
+        let version = reader.read_u8()?;
+        if version != FORMAT_VERSION {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported capture format version {}", version),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            speed,
+            origin: None,
+        })
+    }
+
+    pub fn speed(&self) -> PlaybackSpeed {
+        self.speed
+    }
+
+    /// Changes the playback speed, taking effect from the next datagram.
+    ///
+    /// Fails with [`ErrorKind::InvalidInput`] if `speed` has an invalid factor, leaving the
+    /// current speed in place.
+    pub fn set_speed(&mut self, speed: PlaybackSpeed) -> std::io::Result<()> {
+        self.speed = speed.validate()?;
+        self.origin = None;
+        Ok(())
+    }
+
+    /// Returns the next datagram, sleeping first if required by the playback speed.
+    ///
+    /// Returns `None` once the end of the capture is reached.
+    pub fn next_datagram(&mut self) -> std::io::Result<Option<Datagram>> {
+        let datagram = match self.read_datagram()? {
+            Some(datagram) => datagram,
+            None => return Ok(None),
+        };
+
+        let factor = match self.speed {
+            PlaybackSpeed::RealTime => 1.0,
+            PlaybackSpeed::Accelerated(factor) => factor,
+            PlaybackSpeed::Stepwise => return Ok(Some(datagram)),
+        };
+
+        let (wall_start, capture_start) = *self
+            .origin
+            .get_or_insert((Instant::now(), datagram.timestamp));
+        let offset = datagram
+            .timestamp
+            .checked_sub(capture_start)
+            .unwrap_or_default();
+        let due = wall_start + offset.div_f64(factor);
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+
+        Ok(Some(datagram))
+    }
+
+    // Reads the next record without any pacing
+    fn read_datagram(&mut self) -> std::io::Result<Option<Datagram>> {
+        let timestamp = match self.reader.read_u64::<LittleEndian>() {
+            Ok(micros) => Duration::from_micros(micros),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let length = self.reader.read_u16::<LittleEndian>()?;
+        let mut payload = vec![0u8; length as usize];
+        self.reader.read_exact(&mut payload)?;
+
+        Ok(Some(Datagram { timestamp, payload }))
+    }
+
+    pub(crate) fn into_boxed(self) -> Player<Box<dyn Read + Send>>
+    where
+        R: Send + 'static,
+    {
+        Player {
+            reader: Box::new(self.reader),
+            speed: self.speed,
+            origin: self.origin,
+        }
+    }
+}
+
+impl<R: Read> Iterator for Player<R> {
+    type Item = std::io::Result<Datagram>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_datagram().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn capture() -> Vec<u8> {
+        let mut recorder = Recorder::new(vec![]).unwrap();
+        recorder
+            .record_at(Duration::from_millis(0), b"\x04\x01\x00\x00\x00\x00\x00")
+            .unwrap();
+        recorder
+            .record_at(Duration::from_millis(30), b"second")
+            .unwrap();
+        recorder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn round_trip_capture() {
+        let player = Player::new(Cursor::new(capture()), PlaybackSpeed::Stepwise).unwrap();
+        let datagrams = player.collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].payload, b"\x04\x01\x00\x00\x00\x00\x00");
+        assert_eq!(datagrams[1].timestamp, Duration::from_millis(30));
+        assert_eq!(datagrams[1].payload, b"second");
+    }
+
+    #[test]
+    fn real_time_playback_is_paced() {
+        let mut player = Player::new(Cursor::new(capture()), PlaybackSpeed::RealTime).unwrap();
+        let start = Instant::now();
+        player.next_datagram().unwrap();
+        player.next_datagram().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(player.next_datagram().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_speed_factors() {
+        for factor in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+            let speed = PlaybackSpeed::Accelerated(factor);
+            let res = Player::new(Cursor::new(capture()), speed);
+            assert_eq!(res.err().map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+
+            let mut player = Player::new(Cursor::new(capture()), PlaybackSpeed::Stepwise).unwrap();
+            let err = player.set_speed(speed).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert_eq!(player.speed(), PlaybackSpeed::Stepwise);
+        }
+
+        let mut player = Player::new(Cursor::new(capture()), PlaybackSpeed::Stepwise).unwrap();
+        player.set_speed(PlaybackSpeed::Accelerated(0.5)).unwrap();
+        assert_eq!(player.speed(), PlaybackSpeed::Accelerated(0.5));
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let res = Player::new(Cursor::new(b"PCAP\x01".to_vec()), PlaybackSpeed::Stepwise);
+        assert!(res.is_err());
+    }
+}
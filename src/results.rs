@@ -0,0 +1,471 @@
+//! Session results in the format written by the ACC dedicated server.
+//!
+//! The server saves a JSON file to its `results` directory at the end of each session, which a
+//! lot of league tooling reads. [`ServerResults::from_archive`] builds the same structure from a
+//! session recorded over the Broadcasting API, ready to be written with a serde format crate such
+//! as `serde_json`.
+//!
+//! Some fields can't be known from the Broadcasting API, these are filled in the way the server
+//! fills them when it has no value: Steam IDs are left empty, GUIDs are `-1` and there are never
+//! any penalties. Missing times are `i32::MAX`, as in the server's own files.
+//!
+//! This module is only available with the `serde` feature enabled.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # fn write(ctx: &acbc::session::Context) -> Result<(), Box<dyn std::error::Error>> {
+//! use acbc::results::ServerResults;
+//!
+//! if let Some(session) = ctx.archived_sessions().last() {
+//!     let results = ServerResults::from_archive(session);
+//!     // serde_json::to_writer(std::fs::File::create("results.json")?, &results)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::acc_enum::SessionType;
+use crate::protocol::inbound::{Driver as EntryDriver, Lap as InboundLap};
+use crate::session::{ArchivedSession, CarContext, ClassifiedCar, SECTORS};
+
+/// The time the server writes when none has been set.
+const NO_TIME: i32 = i32::MAX;
+
+/// A complete results file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerResults {
+    /// `FP`, `Q` or `R`.
+    pub session_type: String,
+    pub track_name: String,
+    pub session_index: u16,
+    pub race_weekend_index: u16,
+    pub meta_data: String,
+    pub server_name: String,
+    pub session_result: SessionResult,
+    /// Every lap driven in the session, in the order they were completed.
+    pub laps: Vec<Lap>,
+    pub penalties: Vec<Penalty>,
+    #[serde(rename = "post_race_penalties")]
+    pub post_race_penalties: Vec<Penalty>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResult {
+    #[serde(rename = "bestlap")]
+    pub best_lap: i32,
+    pub best_splits: Vec<i32>,
+    pub is_wet_session: u8,
+    #[serde(rename = "type")]
+    pub result_type: u8,
+    pub leader_board_lines: Vec<LeaderBoardLine>,
+}
+
+/// A car's line in the results, in finishing order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderBoardLine {
+    pub car: Car,
+    pub current_driver: Driver,
+    pub current_driver_index: u16,
+    pub timing: Timing,
+    pub missing_mandatory_pitstop: i32,
+    /// Time in the car for each driver, in milliseconds.
+    pub driver_total_times: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Car {
+    pub car_id: u16,
+    pub race_number: i32,
+    pub car_model: u8,
+    pub cup_category: u8,
+    /// The car's class badge, such as `GT3`.
+    pub car_group: String,
+    pub team_name: String,
+    pub nationality: u16,
+    pub car_guid: i64,
+    pub team_guid: i64,
+    pub drivers: Vec<Driver>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Driver {
+    pub first_name: String,
+    pub last_name: String,
+    pub short_name: String,
+    /// The driver's Steam ID, which isn't available over the Broadcasting API.
+    pub player_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub last_lap: i32,
+    pub last_splits: Vec<i32>,
+    pub best_lap: i32,
+    pub best_splits: Vec<i32>,
+    pub total_time: i64,
+    pub lap_count: u16,
+    pub last_split_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lap {
+    pub car_id: u16,
+    pub driver_index: u16,
+    #[serde(rename = "laptime")]
+    pub lap_time: i32,
+    pub is_valid_for_best: bool,
+    pub splits: Vec<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Penalty {
+    pub car_id: u16,
+    pub driver_index: u16,
+    pub reason: String,
+    pub penalty: String,
+    pub penalty_value: i32,
+    pub violation_in_lap: i32,
+    pub cleared_in_lap: i32,
+}
+
+fn millis(duration: Option<Duration>) -> i32 {
+    duration.map_or(NO_TIME, |d| d.as_millis().min(NO_TIME as u128) as i32)
+}
+
+/// Pads splits out to one per sector, as the server always writes them.
+fn splits(lap: &InboundLap) -> Vec<i32> {
+    (0..SECTORS)
+        .map(|sector| lap.splits.get(sector).copied().unwrap_or(NO_TIME))
+        .collect()
+}
+
+fn is_best_lap_candidate(lap: &InboundLap) -> bool {
+    lap.time().is_some() && lap.is_valid_for_best && !lap.is_invalid
+}
+
+impl From<&EntryDriver<'_>> for Driver {
+    fn from(driver: &EntryDriver<'_>) -> Self {
+        Driver {
+            first_name: driver.first_name.to_string(),
+            last_name: driver.last_name.to_string(),
+            short_name: driver.short_name.to_string(),
+            player_id: String::new(),
+        }
+    }
+}
+
+impl LeaderBoardLine {
+    fn new(result: &ClassifiedCar, car: &CarContext) -> LeaderBoardLine {
+        let entry = car.entry.as_ref();
+        let state = car.state.as_ref();
+        let drivers: Vec<Driver> = entry
+            .map(|e| e.drivers.iter().map(Driver::from).collect())
+            .unwrap_or_default();
+        let current_driver_index = state
+            .map(|s| s.driver_index)
+            .or_else(|| entry.map(|e| e.current_driver_index as u16))
+            .unwrap_or(0);
+
+        let best_lap = car
+            .laps
+            .iter()
+            .map(|completed| &completed.lap)
+            .filter(|lap| is_best_lap_candidate(lap))
+            .min_by_key(|lap| lap.lap_time_ms);
+        let last_lap = car.laps.last().map(|completed| &completed.lap);
+
+        LeaderBoardLine {
+            car: Car {
+                car_id: result.car_id,
+                race_number: entry.map_or(0, |e| e.race_number),
+                car_model: entry.map_or(0, |e| e.model.into()),
+                cup_category: entry.map_or(0, |e| e.cup_category.into()),
+                car_group: entry
+                    .and_then(|e| e.model.class())
+                    .map(|class| class.to_string())
+                    .unwrap_or_default(),
+                team_name: entry.map(|e| e.team_name.to_string()).unwrap_or_default(),
+                nationality: entry.map_or(0, |e| e.nationality.into()),
+                car_guid: -1,
+                team_guid: -1,
+                drivers: drivers.clone(),
+            },
+            current_driver: drivers
+                .get(current_driver_index as usize)
+                .cloned()
+                .unwrap_or_default(),
+            current_driver_index,
+            timing: Timing {
+                last_lap: millis(last_lap.and_then(|lap| lap.time())),
+                last_splits: last_lap
+                    .map(splits)
+                    .unwrap_or_else(|| vec![NO_TIME; SECTORS]),
+                best_lap: millis(best_lap.and_then(|lap| lap.time())),
+                best_splits: (0..SECTORS)
+                    .map(|sector| millis(car.best_sectors.get(sector)))
+                    .collect(),
+                total_time: result.total_time.as_millis() as i64,
+                lap_count: result.laps,
+                last_split_id: 0,
+            },
+            missing_mandatory_pitstop: 0,
+            driver_total_times: (0..drivers.len() as u16)
+                .map(|index| car.driver_time(index).as_millis() as f64)
+                .collect(),
+        }
+    }
+}
+
+impl ServerResults {
+    /// Converts a session recorded by a [`Context`](crate::session::Context), with the leader
+    /// board in the order of its classification.
+    pub fn from_archive(archived: &ArchivedSession) -> ServerResults {
+        let session = &archived.session;
+
+        let leader_board_lines = archived
+            .classification
+            .results
+            .iter()
+            .filter_map(|result| {
+                archived
+                    .car_by_id(result.car_id)
+                    .map(|car| LeaderBoardLine::new(result, car))
+            })
+            .collect();
+
+        // Order every car's laps by when they were completed
+        let mut laps: Vec<(Duration, Lap)> = vec![];
+        for (car_id, car) in &archived.cars {
+            for completed in &car.laps {
+                let lap = &completed.lap;
+                if lap.time().is_none() {
+                    continue;
+                }
+                laps.push((
+                    completed.completed_at,
+                    Lap {
+                        car_id: *car_id,
+                        driver_index: lap.driver_index,
+                        lap_time: lap.lap_time_ms,
+                        is_valid_for_best: is_best_lap_candidate(lap),
+                        splits: splits(lap),
+                    },
+                ));
+            }
+        }
+        laps.sort_by_key(|(completed, lap)| (*completed, lap.car_id));
+        let laps: Vec<Lap> = laps.into_iter().map(|(_, lap)| lap).collect();
+
+        let valid = || laps.iter().filter(|lap| lap.is_valid_for_best);
+        let best_lap = valid().map(|lap| lap.lap_time).min().unwrap_or(NO_TIME);
+        let best_splits = (0..SECTORS)
+            .map(|sector| {
+                valid()
+                    .map(|lap| lap.splits[sector])
+                    .min()
+                    .unwrap_or(NO_TIME)
+            })
+            .collect();
+
+        let session_type = match session.session_type {
+            SessionType::Race => "R",
+            SessionType::Qualifying | SessionType::Superpole | SessionType::HotlapSuperpole => "Q",
+            _ => "FP",
+        };
+        let track_name = archived
+            .track
+            .as_ref()
+            .map(|track| match track.track() {
+                Some(known) => known.config_key().to_string(),
+                None => track.name.to_lowercase(),
+            })
+            .unwrap_or_default();
+
+        ServerResults {
+            session_type: session_type.to_string(),
+            track_name,
+            session_index: session.session_index,
+            race_weekend_index: session.event_index,
+            meta_data: String::new(),
+            server_name: String::new(),
+            session_result: SessionResult {
+                best_lap,
+                best_splits,
+                is_wet_session: (session.rain_level > 0 || session.wetness > 0) as u8,
+                result_type: session.session_type.into(),
+                leader_board_lines,
+            },
+            laps,
+            penalties: vec![],
+            post_race_penalties: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::{CupCategory, SessionPhase};
+    use crate::session::fixtures::{car_update, entry, lap, session_update};
+    use crate::session::Context;
+
+    fn timed_lap(car_id: u16, splits: [i32; 3]) -> InboundLap {
+        let mut lap = lap(car_id, Some(splits.iter().sum()));
+        lap.splits.extend(splits);
+        lap
+    }
+
+    /// A race with each `(session time, car, position, laps completed, last lap)` update.
+    fn race(updates: Vec<(f32, u16, u16, u16, InboundLap)>) -> Context {
+        let mut ctx = Context::new();
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::Session,
+            0.0,
+        ));
+        ctx.update_car_entry(entry(1, 7, CupCategory::Overall));
+        ctx.update_car_entry(entry(2, 99, CupCategory::ProAm));
+
+        for (time, id, position, completed, last_lap) in updates {
+            ctx.update_session(session_update(
+                SessionType::Race,
+                SessionPhase::Session,
+                time,
+            ));
+            let mut update = car_update(id, position, completed, 0.0);
+            update.last_lap = last_lap;
+            ctx.update_car_state(update);
+        }
+        ctx
+    }
+
+    /// Archives a race by starting the next session.
+    fn archive(mut ctx: Context) -> ArchivedSession {
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::SessionOver,
+            0.0,
+        ));
+        let mut next = session_update(SessionType::Practice, SessionPhase::Starting, 0.0);
+        next.event_index = 1;
+        ctx.update_session(next);
+        ctx.archived_sessions()[0].clone()
+    }
+
+    /// A two car race, archived by the start of the next session.
+    fn archived_race() -> ArchivedSession {
+        archive(race(vec![
+            (100_300.0, 2, 2, 1, timed_lap(2, [30_200, 40_100, 30_000])),
+            (100_500.0, 1, 1, 1, timed_lap(1, [30_000, 40_000, 30_500])),
+            (199_300.0, 2, 2, 2, {
+                let mut lap = timed_lap(2, [29_000, 40_000, 30_000]);
+                lap.is_invalid = true;
+                lap
+            }),
+            (200_500.0, 1, 1, 2, timed_lap(1, [29_500, 40_500, 30_000])),
+        ]))
+    }
+
+    #[test]
+    fn results_from_archive() {
+        let results = ServerResults::from_archive(&archived_race());
+        assert_eq!(results.session_type, "R");
+        assert_eq!(results.session_result.result_type, 10);
+        assert_eq!(results.session_result.is_wet_session, 0);
+
+        let lines = &results.session_result.leader_board_lines;
+        assert_eq!(
+            lines.iter().map(|l| l.car.race_number).collect::<Vec<_>>(),
+            vec![7, 99]
+        );
+        let winner = &lines[0];
+        assert_eq!(winner.car.car_group, "GT3");
+        assert_eq!(winner.current_driver.last_name, "Smith 7");
+        assert_eq!(winner.timing.lap_count, 2);
+        assert_eq!(winner.timing.total_time, 200_500);
+        assert_eq!(winner.timing.best_lap, 100_000);
+        assert_eq!(winner.timing.last_splits, vec![29_500, 40_500, 30_000]);
+
+        // The invalid lap doesn't count towards the best times
+        assert_eq!(lines[1].timing.best_lap, 100_300);
+        assert_eq!(results.session_result.best_lap, 100_000);
+        assert_eq!(
+            results.session_result.best_splits,
+            vec![29_500, 40_000, 30_000]
+        );
+
+        assert_eq!(
+            results
+                .laps
+                .iter()
+                .map(|l| (l.car_id, l.lap_time, l.is_valid_for_best))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, 100_300, true),
+                (1, 100_500, true),
+                (2, 99_000, false),
+                (1, 100_000, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn laps_missed_by_the_client() {
+        let results = ServerResults::from_archive(&archive(race(vec![
+            (100_000.0, 1, 1, 1, lap(1, Some(100_000))),
+            // Connected after car 2 had already completed a lap
+            (100_000.0, 2, 2, 2, lap(2, Some(110_000))),
+            (250_000.0, 2, 2, 3, lap(2, Some(150_000))),
+            // Car 1's second lap was missed
+            (300_000.0, 1, 1, 3, lap(1, Some(100_000))),
+        ])));
+
+        let lines = &results.session_result.leader_board_lines;
+        assert_eq!(lines[0].timing.lap_count, 3);
+        assert_eq!(lines[1].timing.lap_count, 3);
+        assert_eq!(
+            results
+                .laps
+                .iter()
+                .map(|l| (l.car_id, l.lap_time))
+                .collect::<Vec<_>>(),
+            vec![(1, 100_000), (2, 110_000), (2, 150_000), (1, 100_000)]
+        );
+    }
+
+    #[test]
+    fn server_field_names() {
+        let results = ServerResults::from_archive(&archived_race());
+        let json = serde_json::to_value(&results).unwrap();
+
+        assert_eq!(json["sessionType"], "R");
+        assert_eq!(json["raceWeekendIndex"], 0);
+        assert!(json["post_race_penalties"].as_array().unwrap().is_empty());
+        assert_eq!(json["sessionResult"]["bestlap"], 100_000);
+        assert_eq!(json["sessionResult"]["type"], 10);
+
+        let line = &json["sessionResult"]["leaderBoardLines"][0];
+        assert_eq!(line["car"]["raceNumber"], 7);
+        assert_eq!(line["car"]["carGuid"], -1);
+        assert_eq!(line["car"]["drivers"][0]["shortName"], "SMI");
+        assert_eq!(line["timing"]["bestSplits"][1], 40_000);
+        assert_eq!(json["laps"][0]["laptime"], 100_300);
+        assert_eq!(json["laps"][0]["isValidForBest"], true);
+
+        let parsed: ServerResults = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, results);
+    }
+}
@@ -1,7 +1,37 @@
 use fnv::FnvHashMap;
 use log::debug;
 
-use crate::protocol::inbound::{self, Driver, EntrylistCar, Lap, RealtimeCarUpdate, TrackData};
+use crate::protocol::acc_enum::{BroadcastingEventType, CarLocation};
+use crate::protocol::inbound::{
+    self, Driver, EntrylistCar, InboundMessage, Lap, RealtimeCarUpdate, RealtimeUpdate, TrackData,
+};
+
+use std::time::Duration;
+
+mod classification;
+mod events;
+mod history;
+mod lap_chart;
+mod pace;
+mod sectors;
+mod standings;
+mod stints;
+mod timing;
+
+use classification::Finish;
+pub use classification::{
+    Classification, ClassificationStatus, ClassifiedCar, DEFAULT_CLASSIFICATION_THRESHOLD,
+};
+pub use events::{SessionEvent, Weather};
+pub use history::ArchivedSession;
+pub use lap_chart::{LapChartLine, LapPosition};
+pub use pace::{Pace, PACE_WINDOW};
+use sectors::SectorTracker;
+pub use sectors::{SectorRating, SectorTimes, SECTORS};
+pub use standings::Standing;
+pub use stints::{PitStop, Stint};
+pub use timing::Gap;
+use timing::ProgressHistory;
 
 /// The state of a Car in the current session
 ///
@@ -9,42 +39,78 @@ use crate::protocol::inbound::{self, Driver, EntrylistCar, Lap, RealtimeCarUpdat
 /// packet, so this is just a type alias to the packet definition.
 pub type CarState = RealtimeCarUpdate;
 
-#[derive(Debug, Clone)]
+/// A lap completed by a car, as stored in [`CarContext::laps`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompletedLap {
+    /// The number of laps the car had completed, including this one.
+    pub number: u16,
+    pub lap: Lap,
+    /// Session time when the lap was stored.
+    pub completed_at: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CarContext {
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "deserialize_owned_entry"))]
     pub entry: Option<EntrylistCar<'static>>,
     pub state: Option<CarState>,
-    pub laps: Vec<(u16, Lap)>,
+    pub laps: Vec<CompletedLap>,
+    pub pit_stops: Vec<PitStop>,
+    /// Driver stints in order, the last of which is still in progress.
+    pub stints: Vec<Stint>,
+    /// The car's best time in each sector, across all of its drivers.
+    pub best_sectors: SectorTimes,
+    /// Positions at the end of each lap, preceded by the grid position if the start was seen.
+    pub lap_positions: Vec<LapPosition>,
+    // The last location reported other than `CarLocation::None`, for spotting pit lane transitions
+    last_location: Option<CarLocation>,
 }
 
 impl CarContext {
     fn new_from_entry(entry: EntrylistCar<'static>) -> CarContext {
         CarContext {
             entry: Some(entry),
-            state: None,
-            laps: vec![],
+            ..CarContext::default()
         }
     }
 
-    fn new_from_update(update: RealtimeCarUpdate) -> CarContext {
-        CarContext {
-            entry: None,
-            state: Some(update),
-            laps: vec![],
-        }
+    pub fn current_driver(&self) -> Option<&Driver<'_>> {
+        self.entry
+            .as_ref()
+            .and_then(|e| e.drivers.get(e.current_driver_index as usize))
     }
+}
 
-    pub fn current_driver(&self) -> Option<&Driver> {
-        self.entry.as_ref().map(|e| {
-            assert!(e.drivers.len() >= e.current_driver_index as usize);
-            &e.drivers[e.current_driver_index as usize]
-        })
-    }
+// Entries borrow from their input by default, so copy them out to keep the context `'static`
+#[cfg(feature = "serde")]
+fn deserialize_owned_entry<'de, D>(
+    deserializer: D,
+) -> Result<Option<EntrylistCar<'static>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+    Ok(Option::<EntrylistCar<'de>>::deserialize(deserializer)?.map(EntrylistCar::into_owned))
 }
 
 #[derive(Default)]
 pub struct Context {
     track: Option<TrackData<'static>>,
+    session: Option<RealtimeUpdate<'static>>,
     cars: FnvHashMap<u16, CarContext>,
+    progress: FnvHashMap<u16, ProgressHistory>,
+    /// Car positions at the last session update.
+    positions: FnvHashMap<u16, u16>,
+    sectors: SectorTracker,
+    archive: Vec<ArchivedSession>,
+    classification: Option<Classification>,
+    classification_threshold: Option<f64>,
+    /// Laps completed by each car at the last session update.
+    laps_at_update: FnvHashMap<u16, u16>,
+    /// Cars which have taken the chequered flag.
+    finishes: FnvHashMap<u16, Finish>,
 }
 
 impl Context {
@@ -52,10 +118,15 @@ impl Context {
         Context::default()
     }
 
-    pub fn track_data(&self) -> Option<&TrackData> {
+    pub fn track_data(&self) -> Option<&TrackData<'_>> {
         self.track.as_ref()
     }
 
+    /// The most recent [`RealtimeUpdate`] received for the session.
+    pub fn session(&self) -> Option<&RealtimeUpdate<'_>> {
+        self.session.as_ref()
+    }
+
     pub fn car_by_id(&self, id: u16) -> Option<&CarContext> {
         self.cars.get(&id)
     }
@@ -72,6 +143,49 @@ impl Context {
         })
     }
 
+    /// Applies the bookkeeping for a decoded message, regardless of which client received it,
+    /// returning any events derived from it.
+    pub(crate) fn process_message(&mut self, message: &InboundMessage) -> Vec<SessionEvent> {
+        match message {
+            InboundMessage::RealtimeCarUpdate(update) => {
+                return self.update_car_state(update.clone())
+            }
+            InboundMessage::RealtimeUpdate(update) => return self.update_session(update.clone()),
+            InboundMessage::EntrylistUpdate(list) => self.seed_entrylist(list),
+            InboundMessage::EntrylistCar(car) => self.update_car_entry(car.clone()),
+            InboundMessage::TrackData(track) => self.update_track_data(track.clone()),
+            InboundMessage::BroadcastingEvent(event) => {
+                if event.event_type == BroadcastingEventType::SessionOver {
+                    self.classify();
+                }
+            }
+            InboundMessage::RegistrationResult(_) => (),
+        }
+        vec![]
+    }
+
+    /// The latest session time, or zero before the first [`RealtimeUpdate`].
+    fn session_time(&self) -> Duration {
+        self.session.as_ref().map_or(Duration::default(), |s| {
+            Duration::from_secs_f64(s.session_time.max(0.0) as f64 / 1000.0)
+        })
+    }
+
+    pub(crate) fn update_session(&mut self, update: RealtimeUpdate) -> Vec<SessionEvent> {
+        let mut events = vec![];
+        self.check_new_session(&update, &mut events);
+        events.extend(self.session_events(&update));
+        let previous_phase = self.session.as_ref().map(|s| s.session_phase);
+        self.session = Some(update.into_owned());
+        self.check_session_over(previous_phase);
+        self.laps_at_update = self
+            .cars
+            .iter()
+            .filter_map(|(&id, car)| car.state.as_ref().map(|s| (id, s.laps)))
+            .collect();
+        events
+    }
+
     pub(crate) fn update_track_data(&mut self, track_data: inbound::TrackData) {
         self.track = Some(track_data.into_owned());
     }
@@ -87,10 +201,13 @@ impl Context {
 
         // Retain only the car IDs still in the entry list
         self.cars.retain(|&k, _| update.car_ids.contains(&k));
+        self.progress.retain(|k, _| update.car_ids.contains(k));
+        self.positions.retain(|k, _| update.car_ids.contains(k));
+        self.sectors.retain_cars(&update.car_ids);
     }
 
     pub(crate) fn update_car_entry(&mut self, updated_car: EntrylistCar) {
-        if let Some(mut e) = self.cars.get_mut(&updated_car.id) {
+        if let Some(e) = self.cars.get_mut(&updated_car.id) {
             e.entry = Some(updated_car.into_owned());
         } else {
             self.cars.insert(
@@ -100,29 +217,137 @@ impl Context {
         }
     }
 
-    pub(crate) fn update_car_state(&mut self, update: RealtimeCarUpdate) {
-        if let Some(mut e) = self.cars.get_mut(&update.id) {
-            // Check if a lap has been completed
-            if let Some(ref previous) = e.state {
-                if update.laps > previous.laps {
-                    debug!("Storing new lap {} for car {}", update.laps, update.id);
-                    e.laps.push((update.laps, update.last_lap));
-                }
-            }
-            // Overwrite the state with the new snapshot
-            e.state = Some(update);
-        } else {
-            self.cars
-                .insert(update.id, CarContext::new_from_update(update.clone()));
-            // We might be connecting mid-session with a lap already completed
-            if update.laps > 0 {
-                debug!("Storing new lap {} for car {}", update.laps, update.id);
-                self.cars
-                    .get_mut(&update.id)
-                    .unwrap()
-                    .laps
-                    .push((update.laps, update.last_lap));
-            }
+    pub(crate) fn update_car_state(&mut self, update: RealtimeCarUpdate) -> Vec<SessionEvent> {
+        if let Some(session) = &self.session {
+            self.progress
+                .entry(update.id)
+                .or_default()
+                .record(session.session_time, &update);
+        }
+
+        let mut events = self.car_events(&update);
+        self.track_sectors(&update, &mut events);
+        let session_time = self.session_time();
+        let on_grid = self.on_grid();
+        let car = self.cars.entry(update.id).or_default();
+        car.track_stints(&update, session_time, &mut events);
+
+        // Check if a lap has been completed, we might be connecting mid-session with a lap
+        // already completed
+        let previous_laps = car.state.as_ref().map_or(0, |s| s.laps);
+        let lap_completed = update.laps > previous_laps;
+        if lap_completed {
+            debug!("Storing new lap {} for car {}", update.laps, update.id);
+            car.laps.push(CompletedLap {
+                number: update.laps,
+                lap: update.last_lap,
+                completed_at: session_time,
+            });
+        }
+        car.record_position(&update, lap_completed, on_grid);
+        let (id, position) = (update.id, update.position);
+        // Overwrite the state with the new snapshot
+        car.state = Some(update);
+
+        if lap_completed {
+            self.check_finish(id, position);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use crate::protocol::acc_enum::{
+        CarLocation, CarModel, CupCategory, DriverCategory, Nationality, SessionPhase, SessionType,
+    };
+    use crate::protocol::inbound::{Driver, EntrylistCar, Lap, RealtimeCarUpdate, RealtimeUpdate};
+    use tinyvec::ArrayVec;
+
+    pub(crate) fn entry(
+        id: u16,
+        race_number: i32,
+        cup_category: CupCategory,
+    ) -> EntrylistCar<'static> {
+        EntrylistCar {
+            id,
+            model: CarModel::Ferrari488Evo,
+            team_name: format!("Team {}", race_number).into(),
+            race_number,
+            cup_category,
+            current_driver_index: 0,
+            nationality: Nationality::GreatBritain,
+            drivers: vec![Driver {
+                first_name: "John".into(),
+                last_name: format!("Smith {}", race_number).into(),
+                short_name: "SMI".into(),
+                category: DriverCategory::Gold,
+                nationality: Nationality::GreatBritain,
+            }],
+        }
+    }
+
+    /// A completed lap, or the placeholder sent for missing laps when `lap_time_ms` is `None`.
+    pub(crate) fn lap(car_id: u16, lap_time_ms: Option<i32>) -> Lap {
+        Lap {
+            lap_time_ms: lap_time_ms.unwrap_or(i32::MAX),
+            car_id,
+            driver_index: 0,
+            splits: ArrayVec::new(),
+            is_invalid: false,
+            is_valid_for_best: true,
+            is_out_lap: false,
+            is_in_lap: false,
+        }
+    }
+
+    pub(crate) fn car_update(id: u16, position: u16, laps: u16, spline: f32) -> RealtimeCarUpdate {
+        RealtimeCarUpdate {
+            id,
+            driver_index: 0,
+            driver_count: 1,
+            gear: 4,
+            world_pos_x: 0.0,
+            world_pos_y: 0.0,
+            yaw: 0.0,
+            car_location: CarLocation::Track,
+            speed_kph: 200,
+            position,
+            cup_position: position,
+            track_position: position,
+            spline_position: spline,
+            laps,
+            delta: 0,
+            best_session_lap: lap(id, None),
+            last_lap: lap(id, None),
+            current_lap: lap(id, None),
+        }
+    }
+
+    pub(crate) fn session_update(
+        session_type: SessionType,
+        session_phase: SessionPhase,
+        session_time: f32,
+    ) -> RealtimeUpdate<'static> {
+        RealtimeUpdate {
+            event_index: 0,
+            session_index: 0,
+            session_type,
+            session_phase,
+            session_time,
+            session_end_time: 0.0,
+            focused_car_index: 0,
+            active_camera_set: "".into(),
+            active_camera: "".into(),
+            current_hud_page: "".into(),
+            replay_info: None,
+            time_of_day: 0.0,
+            ambient_temp: 20,
+            track_temp: 25,
+            clouds: 0,
+            rain_level: 0,
+            wetness: 0,
+            best_session_lap: lap(0, None),
         }
     }
 }
@@ -137,6 +362,7 @@ mod tests {
     fn context_with_entries() -> Context {
         let mut ctx = Context::new();
         let update = EntrylistUpdate {
+            connection_id: 1,
             car_ids: vec![1001, 1002],
         };
 
@@ -192,6 +418,7 @@ mod tests {
         assert!(ctx.track_data().is_none());
 
         let data = TrackData {
+            connection_id: 1,
             name: "Spa".into(),
             id: 42,
             distance: 4812,
@@ -206,6 +433,7 @@ mod tests {
 
         // Overwrite the existing structure
         let data = TrackData {
+            connection_id: 1,
             name: "Silverstone".into(),
             id: 36,
             distance: 3210,
@@ -244,16 +472,17 @@ mod tests {
                 .team_name,
             "Team 2"
         );
-        assert!(ctx.cars.get(&1003).is_none());
+        assert!(!ctx.cars.contains_key(&1003));
 
         // Check that one one car gets pruned
         let update = EntrylistUpdate {
+            connection_id: 1,
             car_ids: vec![1002],
         };
         ctx.seed_entrylist(&update);
 
         // ID 1002 is still present and retains its data
-        assert!(ctx.cars.get(&1002).is_some());
+        assert!(ctx.cars.contains_key(&1002));
         assert_eq!(
             ctx.cars
                 .get(&1002)
@@ -266,7 +495,7 @@ mod tests {
         );
 
         // ID 1001 has been pruned
-        assert!(ctx.cars.get(&1001).is_none());
+        assert!(!ctx.cars.contains_key(&1001));
     }
 
     #[test]
@@ -297,4 +526,17 @@ mod tests {
         assert_eq!(d1.unwrap().first_name, "John");
         assert_eq!(d2.unwrap().first_name, "Jane");
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn car_context_deserializes_owned() {
+        let ctx = context_with_entries();
+        let car = ctx.car_by_id(1001).unwrap();
+
+        let json = serde_json::to_string(car).unwrap();
+        let restored: CarContext = serde_json::from_str(&json).unwrap();
+        drop(json);
+
+        assert_eq!(restored.entry, car.entry);
+    }
 }
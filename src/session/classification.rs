@@ -0,0 +1,560 @@
+//! The final classification of a session.
+
+use std::cmp::Reverse;
+use std::time::Duration;
+
+use fnv::FnvHashMap;
+
+use super::{CarContext, Context, Gap, Standing};
+use crate::protocol::acc_enum::{CarLocation, CupCategory, SessionPhase, SessionType};
+
+/// The share of the winner's laps a car must complete to be classified in a race, by default.
+pub const DEFAULT_CLASSIFICATION_THRESHOLD: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClassificationStatus {
+    Classified,
+    /// Still running at the end, but without enough laps, or without a lap time outside of a race.
+    NotClassified,
+    /// Retired to the pits without enough laps.
+    DidNotFinish,
+}
+
+/// One car's result.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClassifiedCar {
+    pub car_id: u16,
+    /// Finishing position, starting from 1, with unclassified cars after the classified ones.
+    pub position: usize,
+    /// Finishing position within the car's cup category.
+    pub class_position: usize,
+    pub race_number: Option<i32>,
+    pub cup_category: Option<CupCategory>,
+    /// Laps completed up to the chequered flag.
+    pub laps: u16,
+    /// The sum of the lap times recorded for the car up to the chequered flag.
+    pub total_time: Duration,
+    /// `None` for the winner.
+    pub gap_to_winner: Option<Gap>,
+    pub best_lap: Option<Duration>,
+    pub status: ClassificationStatus,
+}
+
+/// The results of a session, taken when it ended.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Classification {
+    pub session_type: SessionType,
+    pub results: Vec<ClassifiedCar>,
+}
+
+impl Classification {
+    pub fn winner(&self) -> Option<&ClassifiedCar> {
+        self.results
+            .first()
+            .filter(|c| c.status == ClassificationStatus::Classified)
+    }
+
+    /// The results of the cars in one cup category, in finishing order.
+    pub fn class_results(&self, cup_category: CupCategory) -> Vec<&ClassifiedCar> {
+        self.results
+            .iter()
+            .filter(|c| c.cup_category == Some(cup_category))
+            .collect()
+    }
+}
+
+/// A car's laps and time when it took the chequered flag, which later laps don't count towards.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Finish {
+    laps: u16,
+    total_time: Duration,
+    /// 0 for the first car to finish.
+    order: usize,
+}
+
+fn total_time(car: &CarContext) -> Duration {
+    car.laps
+        .iter()
+        .filter_map(|completed| completed.lap.time())
+        .sum()
+}
+
+fn result(standing: &Standing<'_>) -> ClassifiedCar {
+    ClassifiedCar {
+        car_id: standing.car_id,
+        position: 0,
+        class_position: 0,
+        race_number: standing.race_number,
+        cup_category: standing.cup_category,
+        laps: standing.laps,
+        total_time: total_time(standing.car),
+        gap_to_winner: None,
+        best_lap: standing.best_lap,
+        status: ClassificationStatus::Classified,
+    }
+}
+
+/// Race gaps are in laps when the car was lapped, otherwise in total time.
+fn gap_between(winner: &ClassifiedCar, car: &ClassifiedCar, is_race: bool) -> Gap {
+    if is_race {
+        let laps = winner.laps.saturating_sub(car.laps);
+        Gap {
+            time: if laps == 0 {
+                car.total_time.checked_sub(winner.total_time)
+            } else {
+                None
+            },
+            laps: laps as f64,
+        }
+    } else {
+        Gap {
+            time: car
+                .best_lap
+                .zip(winner.best_lap)
+                .and_then(|(car, winner)| car.checked_sub(winner)),
+            laps: 0.0,
+        }
+    }
+}
+
+impl Context {
+    /// The classification taken when the current session ended, `None` while it is running.
+    ///
+    /// It is updated as each car takes the chequered flag, at the first time it crosses the line
+    /// once the session is over. In a race the flag only falls once the leader has crossed.
+    pub fn classification(&self) -> Option<&Classification> {
+        self.classification.as_ref()
+    }
+
+    /// Sets the share of the winner's laps, from 0 to 1, which a car must complete to be
+    /// classified in a race.
+    pub fn set_classification_threshold(&mut self, threshold: f64) {
+        self.classification_threshold = Some(threshold.clamp(0.0, 1.0));
+    }
+
+    /// Takes the classification if the session has just ended.
+    pub(super) fn check_session_over(&mut self, previous_phase: Option<SessionPhase>) {
+        let phase = self.session.as_ref().map(|s| s.session_phase);
+        let ended = matches!(
+            phase,
+            Some(SessionPhase::SessionOver) | Some(SessionPhase::ResultUi)
+        );
+        if !ended || phase == previous_phase {
+            return;
+        }
+
+        let was_over = matches!(
+            previous_phase,
+            Some(SessionPhase::SessionOver)
+                | Some(SessionPhase::PostSession)
+                | Some(SessionPhase::ResultUi)
+        );
+        if !was_over && self.is_race() {
+            // The session can end as the leader crosses the line, after the car update for the
+            // crossing, so cars which crossed since the last session update may have finished
+            let mut crossed: Vec<(u16, u16)> = self
+                .cars
+                .iter()
+                .filter_map(|(&id, car)| {
+                    let state = car.state.as_ref()?;
+                    let before = *self.laps_at_update.get(&id)?;
+                    if state.laps > before {
+                        Some((state.position, id))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            crossed.sort_unstable();
+            if crossed.first().map(|&(position, _)| position) == Some(1) {
+                for (_, id) in crossed {
+                    self.finish(id);
+                }
+            }
+        }
+        self.classify();
+    }
+
+    /// Records a car as finished if it has crossed the line for the last time, having just
+    /// completed a lap in `position`.
+    pub(super) fn check_finish(&mut self, car_id: u16, position: u16) {
+        if !self.is_over() || self.finishes.contains_key(&car_id) {
+            return;
+        }
+        // Nobody has finished a race until the leader takes the flag, outside of races each car
+        // finishes the lap it was on when time ran out
+        if self.is_race() && self.finishes.is_empty() && position != 1 {
+            return;
+        }
+        self.finish(car_id);
+        self.classify();
+    }
+
+    fn finish(&mut self, car_id: u16) {
+        let car = match self.cars.get(&car_id) {
+            Some(car) => car,
+            None => return,
+        };
+        let finish = Finish {
+            laps: car.state.as_ref().map_or(0, |s| s.laps),
+            total_time: total_time(car),
+            order: self.finishes.len(),
+        };
+        self.finishes.entry(car_id).or_insert(finish);
+    }
+
+    fn is_race(&self) -> bool {
+        self.session
+            .as_ref()
+            .map(|s| s.session_type == SessionType::Race)
+            .unwrap_or(false)
+    }
+
+    fn is_over(&self) -> bool {
+        matches!(
+            self.session.as_ref().map(|s| s.session_phase),
+            Some(SessionPhase::SessionOver)
+                | Some(SessionPhase::PostSession)
+                | Some(SessionPhase::ResultUi)
+        )
+    }
+
+    /// Builds the classification from the current standings, replacing any previous one.
+    pub(super) fn classify(&mut self) {
+        let session_type = match &self.session {
+            Some(session) => session.session_type,
+            None => return,
+        };
+        self.classification = Some(self.build_classification(session_type));
+    }
+
+    pub(super) fn build_classification(&self, session_type: SessionType) -> Classification {
+        let is_race = session_type == SessionType::Race;
+        let threshold = self
+            .classification_threshold
+            .unwrap_or(DEFAULT_CLASSIFICATION_THRESHOLD);
+
+        let standings = self.standings();
+        let mut cars: Vec<(&Standing<'_>, ClassifiedCar)> = standings
+            .iter()
+            .map(|standing| {
+                let mut car = result(standing);
+                // Laps after the chequered flag don't count
+                if let Some(finish) = self.finishes.get(&car.car_id) {
+                    car.laps = finish.laps;
+                    car.total_time = finish.total_time;
+                }
+                (standing, car)
+            })
+            .collect();
+        if is_race {
+            // Stable, so cars which haven't finished keep their order from the standings
+            cars.sort_by_key(|(_, car)| {
+                let order = self.finishes.get(&car.car_id).map(|f| f.order);
+                (Reverse(car.laps), order.unwrap_or(usize::MAX))
+            });
+        }
+
+        let winner_laps = cars.first().map_or(0, |(_, car)| car.laps);
+        let mut results: Vec<ClassifiedCar> = cars
+            .into_iter()
+            .map(|(standing, mut car)| {
+                car.status = if !is_race {
+                    match car.best_lap {
+                        Some(_) => ClassificationStatus::Classified,
+                        None => ClassificationStatus::NotClassified,
+                    }
+                } else if car.laps as f64 >= winner_laps as f64 * threshold && car.laps > 0 {
+                    ClassificationStatus::Classified
+                } else if standing.location == Some(CarLocation::Track) {
+                    ClassificationStatus::NotClassified
+                } else {
+                    ClassificationStatus::DidNotFinish
+                };
+                car
+            })
+            .collect();
+
+        // Stable, so the standings order is kept within each group
+        results.sort_by_key(|c| c.status != ClassificationStatus::Classified);
+
+        let mut class_counts = FnvHashMap::default();
+        let winner = results.first().cloned();
+        for (index, car) in results.iter_mut().enumerate() {
+            car.position = index + 1;
+            let count = class_counts.entry(car.cup_category).or_insert(0);
+            *count += 1;
+            car.class_position = *count;
+
+            if index > 0 {
+                car.gap_to_winner = winner.as_ref().map(|w| gap_between(w, car, is_race));
+            }
+        }
+
+        Classification {
+            session_type,
+            results,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::BroadcastingEventType;
+    use crate::protocol::inbound::{BroadcastingEvent, InboundMessage};
+    use crate::session::fixtures::{car_update, entry, lap, session_update};
+
+    /// Runs `laps` laps of `lap_ms` each for a car, finishing in `position` at `location`.
+    fn run(
+        ctx: &mut Context,
+        id: u16,
+        position: u16,
+        laps: u16,
+        lap_ms: i32,
+        location: CarLocation,
+    ) {
+        for completed in 0..=laps {
+            let mut update = car_update(id, position, completed, 0.0);
+            update.last_lap = lap(id, Some(lap_ms));
+            update.best_session_lap = lap(id, Some(lap_ms));
+            update.car_location = if completed == laps {
+                location
+            } else {
+                CarLocation::Track
+            };
+            ctx.update_car_state(update);
+        }
+    }
+
+    fn race() -> Context {
+        let mut ctx = Context::new();
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::Session,
+            0.0,
+        ));
+        for (id, cup) in [
+            (1, CupCategory::Overall),
+            (2, CupCategory::ProAm),
+            (3, CupCategory::Overall),
+            (4, CupCategory::ProAm),
+            (5, CupCategory::Overall),
+        ] {
+            ctx.update_car_entry(entry(id, id as i32 * 10, cup));
+        }
+        run(&mut ctx, 1, 1, 10, 100_000, CarLocation::Track);
+        run(&mut ctx, 2, 2, 10, 100_500, CarLocation::Track);
+        run(&mut ctx, 3, 3, 9, 101_000, CarLocation::Pitlane);
+        run(&mut ctx, 5, 4, 5, 100_000, CarLocation::Pitlane);
+        run(&mut ctx, 4, 5, 5, 120_000, CarLocation::Track);
+        ctx
+    }
+
+    #[test]
+    fn race_classification() {
+        let mut ctx = race();
+        assert!(ctx.classification().is_none());
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::SessionOver,
+            0.0,
+        ));
+
+        let classification = ctx.classification().unwrap();
+        let summary: Vec<(u16, usize, usize, ClassificationStatus)> = classification
+            .results
+            .iter()
+            .map(|c| (c.car_id, c.position, c.class_position, c.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, 1, 1, ClassificationStatus::Classified),
+                (2, 2, 1, ClassificationStatus::Classified),
+                (3, 3, 2, ClassificationStatus::Classified),
+                (5, 4, 3, ClassificationStatus::DidNotFinish),
+                (4, 5, 2, ClassificationStatus::NotClassified),
+            ]
+        );
+
+        let winner = classification.winner().unwrap();
+        assert_eq!(winner.laps, 10);
+        assert_eq!(winner.total_time, Duration::from_secs(1000));
+        assert_eq!(winner.best_lap, Some(Duration::from_secs(100)));
+        assert_eq!(winner.race_number, Some(10));
+        assert!(winner.gap_to_winner.is_none());
+
+        let second = &classification.results[1];
+        assert_eq!(
+            second.gap_to_winner,
+            Some(Gap {
+                time: Some(Duration::from_secs(5)),
+                laps: 0.0
+            })
+        );
+        assert_eq!(
+            classification.results[2].gap_to_winner,
+            Some(Gap {
+                time: None,
+                laps: 1.0
+            })
+        );
+
+        let pro_am = classification.class_results(CupCategory::ProAm);
+        assert_eq!(
+            pro_am.iter().map(|c| c.car_id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+    }
+
+    /// Completes a lap of `lap_ms` for a car running in `position`.
+    fn cross(ctx: &mut Context, id: u16, position: u16, laps: u16, lap_ms: i32) {
+        let mut update = car_update(id, position, laps, 0.0);
+        update.last_lap = lap(id, Some(lap_ms));
+        ctx.update_car_state(update);
+    }
+
+    fn summary(ctx: &Context) -> Vec<(u16, u16, Duration)> {
+        ctx.classification()
+            .unwrap()
+            .results
+            .iter()
+            .map(|c| (c.car_id, c.laps, c.total_time))
+            .collect()
+    }
+
+    #[test]
+    fn cars_finish_at_their_own_line_crossing() {
+        let mut ctx = Context::new();
+        let race = |phase| session_update(SessionType::Race, phase, 0.0);
+        ctx.update_session(race(SessionPhase::Session));
+        for laps in 1..=4 {
+            cross(&mut ctx, 1, 1, laps, 100_000);
+            cross(&mut ctx, 2, 2, laps, 101_000);
+            ctx.update_session(race(SessionPhase::Session));
+        }
+
+        // The session ends as the leader completes lap 5, with car 2 still on its last lap
+        cross(&mut ctx, 1, 1, 5, 100_000);
+        ctx.update_session(race(SessionPhase::SessionOver));
+        assert_eq!(
+            summary(&ctx),
+            vec![
+                (1, 5, Duration::from_secs(500)),
+                (2, 4, Duration::from_secs(404)),
+            ]
+        );
+
+        // Car 2 takes the flag after the phase change, and cool-down laps don't count
+        cross(&mut ctx, 2, 2, 5, 101_000);
+        cross(&mut ctx, 1, 1, 6, 130_000);
+        cross(&mut ctx, 2, 2, 6, 135_000);
+        ctx.update_session(race(SessionPhase::ResultUi));
+        assert_eq!(
+            summary(&ctx),
+            vec![
+                (1, 5, Duration::from_secs(500)),
+                (2, 5, Duration::from_secs(505)),
+            ]
+        );
+        let results = &ctx.classification().unwrap().results;
+        assert_eq!(
+            results[1].gap_to_winner,
+            Some(Gap {
+                time: Some(Duration::from_secs(5)),
+                laps: 0.0
+            })
+        );
+        assert!(results
+            .iter()
+            .all(|c| c.status == ClassificationStatus::Classified));
+    }
+
+    #[test]
+    fn flag_falls_when_the_leader_crosses() {
+        let mut ctx = Context::new();
+        let race = |phase| session_update(SessionType::Race, phase, 0.0);
+        ctx.update_session(race(SessionPhase::Session));
+        for laps in 1..=4 {
+            cross(&mut ctx, 1, 1, laps, 100_000);
+            cross(&mut ctx, 2, 2, laps.min(3), 150_000);
+            ctx.update_session(race(SessionPhase::Session));
+        }
+        // Time runs out with the leader part way round its lap
+        ctx.update_session(race(SessionPhase::SessionOver));
+
+        // Car 2 crosses the line before the leader, so it hasn't finished and runs one more lap
+        cross(&mut ctx, 2, 2, 4, 150_000);
+        cross(&mut ctx, 1, 1, 5, 100_000);
+        cross(&mut ctx, 2, 2, 5, 150_000);
+        cross(&mut ctx, 2, 2, 6, 150_000);
+        cross(&mut ctx, 1, 1, 6, 130_000);
+        ctx.update_session(race(SessionPhase::ResultUi));
+
+        assert_eq!(
+            summary(&ctx),
+            vec![
+                (1, 5, Duration::from_secs(500)),
+                (2, 5, Duration::from_secs(750)),
+            ]
+        );
+    }
+
+    #[test]
+    fn threshold_and_session_over_event() {
+        let mut ctx = race();
+        ctx.set_classification_threshold(0.5);
+        ctx.process_message(&InboundMessage::BroadcastingEvent(BroadcastingEvent {
+            event_type: BroadcastingEventType::SessionOver,
+            message: "".into(),
+            time_ms: 0,
+            car_id: 0,
+        }));
+
+        let classification = ctx.classification().unwrap();
+        assert!(classification
+            .results
+            .iter()
+            .all(|c| c.status == ClassificationStatus::Classified));
+
+        // The classification goes into the archive with the session
+        let mut next = session_update(SessionType::Race, SessionPhase::Starting, 0.0);
+        next.session_index = 1;
+        ctx.update_session(next);
+        assert!(ctx.classification().is_none());
+        assert_eq!(ctx.archived_sessions()[0].classification.results.len(), 5);
+    }
+
+    #[test]
+    fn qualifying_classification() {
+        let mut ctx = Context::new();
+        ctx.update_session(session_update(
+            SessionType::Qualifying,
+            SessionPhase::Session,
+            0.0,
+        ));
+        run(&mut ctx, 1, 1, 3, 90_000, CarLocation::Pitlane);
+        run(&mut ctx, 2, 2, 4, 90_750, CarLocation::Track);
+        ctx.update_car_state(car_update(3, 3, 0, 0.0));
+        ctx.update_session(session_update(
+            SessionType::Qualifying,
+            SessionPhase::ResultUi,
+            0.0,
+        ));
+
+        let results = &ctx.classification().unwrap().results;
+        assert_eq!(
+            results[1].gap_to_winner,
+            Some(Gap {
+                time: Some(Duration::from_millis(750)),
+                laps: 0.0
+            })
+        );
+        assert_eq!(results[0].status, ClassificationStatus::Classified);
+        assert_eq!(results[2].status, ClassificationStatus::NotClassified);
+    }
+}
@@ -0,0 +1,360 @@
+//! Events derived by the [`Context`](super::Context) from consecutive updates.
+
+use std::time::Duration;
+
+use fnv::FnvHashMap;
+
+use super::{CarState, Context, PitStop, SectorRating, Stint};
+use crate::protocol::acc_enum::{CarLocation, SessionPhase, SessionType};
+use crate::protocol::inbound::RealtimeUpdate;
+
+/// Cars on track at or below this speed are considered to have stopped.
+const STOPPED_SPEED_KPH: u16 = 5;
+
+/// Something which happened in the session, detected by comparing the latest updates with the
+/// previous ones.
+///
+/// These are delivered to [`MessageHandler`](crate::client::MessageHandler) callbacks by the
+/// synchronous client, after the callback for the message which caused them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SessionEvent {
+    /// A car has entered the pit lane, having completed `lap` laps.
+    PitEntry {
+        car_id: u16,
+        lap: u16,
+    },
+    /// A car has rejoined the track after a pit stop.
+    PitExit {
+        car_id: u16,
+        stop: PitStop,
+    },
+    /// A different driver has taken over the car, ending the previous driver's stint.
+    StintFinished {
+        car_id: u16,
+        stint: Stint,
+    },
+    /// A car's overall position has changed since the previous session update.
+    PositionChanged {
+        car_id: u16,
+        from: u16,
+        to: u16,
+    },
+    /// `car_id` has passed `overtaken_car_id` on track. Only reported while a race is running, as
+    /// other sessions are ordered by best lap rather than by who is ahead on the road.
+    Overtake {
+        car_id: u16,
+        overtaken_car_id: u16,
+    },
+    /// A car has come to a halt on the track while the session is running.
+    CarStopped {
+        car_id: u16,
+    },
+    /// Drivers are indexes into the car's entry.
+    DriverSwap {
+        car_id: u16,
+        from: u16,
+        to: u16,
+    },
+    SessionPhaseChanged {
+        from: SessionPhase,
+        to: SessionPhase,
+    },
+    WeatherChanged {
+        from: Weather,
+        to: Weather,
+    },
+    /// A new session has started, and the previous one has been archived at `index` in
+    /// [`Context::archived_sessions`].
+    SessionArchived {
+        index: usize,
+    },
+    /// A car has completed a sector, counting from 0.
+    SectorCompleted {
+        car_id: u16,
+        sector: usize,
+        time: Duration,
+        rating: SectorRating,
+    },
+}
+
+/// The track conditions reported with each [`RealtimeUpdate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Weather {
+    pub rain_level: u8,
+    pub wetness: u8,
+}
+
+impl Weather {
+    fn of(update: &RealtimeUpdate) -> Weather {
+        Weather {
+            rain_level: update.rain_level,
+            wetness: update.wetness,
+        }
+    }
+}
+
+impl Context {
+    /// Compares a new session update with the previous one, and the car positions with those at
+    /// the previous session update.
+    pub(super) fn session_events(&mut self, update: &RealtimeUpdate) -> Vec<SessionEvent> {
+        let mut events = vec![];
+
+        if let Some(previous) = &self.session {
+            if previous.session_phase != update.session_phase {
+                events.push(SessionEvent::SessionPhaseChanged {
+                    from: previous.session_phase,
+                    to: update.session_phase,
+                });
+            }
+            let (from, to) = (Weather::of(previous), Weather::of(update));
+            if from != to {
+                events.push(SessionEvent::WeatherChanged { from, to });
+            }
+        }
+
+        // ACC sends the car updates between session updates, so this compares whole rounds
+        let positions: FnvHashMap<u16, u16> = self
+            .cars
+            .iter()
+            .filter_map(|(&id, car)| car.state.as_ref().map(|s| (id, s.position)))
+            .filter(|&(_, position)| position > 0)
+            .collect();
+        let moved = positions
+            .iter()
+            .filter_map(|(&id, &to)| match self.positions.get(&id) {
+                Some(&from) if from != to => Some((id, from, to)),
+                _ => None,
+            });
+
+        let mut changes: Vec<(u16, u16, u16)> = moved.collect();
+        changes.sort_by_key(|&(_, _, to)| to);
+        for &(car_id, from, to) in &changes {
+            events.push(SessionEvent::PositionChanged { car_id, from, to });
+        }
+
+        let racing = update.session_type == SessionType::Race
+            && update.session_phase == SessionPhase::Session;
+        let gains = changes.iter().filter(|(_, from, to)| racing && to < from);
+        for &(car_id, from, to) in gains {
+            if !self.on_track(car_id) {
+                continue;
+            }
+            let mut overtaken: Vec<(u16, u16)> = positions
+                .iter()
+                .filter(|&(&other, _)| other != car_id && self.on_track(other))
+                .filter_map(|(&other, &now)| {
+                    let before = *self.positions.get(&other)?;
+                    // Ahead of this car before, and behind it now
+                    if before < from && now > to {
+                        Some((other, now))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            overtaken.sort_by_key(|&(_, now)| now);
+            events.extend(overtaken.into_iter().map(|(overtaken_car_id, _)| {
+                SessionEvent::Overtake {
+                    car_id,
+                    overtaken_car_id,
+                }
+            }));
+        }
+
+        self.positions = positions;
+        events
+    }
+
+    /// Compares a new car update with the car's previous state.
+    pub(super) fn car_events(&self, update: &CarState) -> Vec<SessionEvent> {
+        let mut events = vec![];
+        let previous = match self.cars.get(&update.id).and_then(|c| c.state.as_ref()) {
+            Some(previous) => previous,
+            None => return events,
+        };
+
+        if previous.driver_index != update.driver_index {
+            events.push(SessionEvent::DriverSwap {
+                car_id: update.id,
+                from: previous.driver_index,
+                to: update.driver_index,
+            });
+        }
+
+        let running = self
+            .session
+            .as_ref()
+            .map(|s| s.session_phase == SessionPhase::Session)
+            .unwrap_or(false);
+        let stopped = |state: &CarState| {
+            state.car_location == CarLocation::Track && state.speed_kph <= STOPPED_SPEED_KPH
+        };
+        if running && stopped(update) && !stopped(previous) {
+            events.push(SessionEvent::CarStopped { car_id: update.id });
+        }
+
+        events
+    }
+
+    fn on_track(&self, car_id: u16) -> bool {
+        self.cars
+            .get(&car_id)
+            .and_then(|c| c.state.as_ref())
+            .map(|s| s.car_location == CarLocation::Track)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::SessionType;
+    use crate::session::fixtures::{car_update, session_update};
+
+    fn session(phase: SessionPhase) -> RealtimeUpdate<'static> {
+        session_update(SessionType::Race, phase, 1000.0)
+    }
+
+    #[test]
+    fn positions_and_overtakes() {
+        let mut ctx = Context::new();
+        ctx.update_session(session(SessionPhase::Session));
+        for (id, position) in [(1, 1), (2, 2), (3, 3), (4, 4)] {
+            ctx.update_car_state(car_update(id, position, 1, 0.5));
+        }
+        assert!(ctx
+            .update_session(session(SessionPhase::Session))
+            .is_empty());
+
+        // Car 4 passes cars 2 and 3, while car 1 pits and drops behind everyone
+        let mut pitting = car_update(1, 4, 1, 0.6);
+        pitting.car_location = CarLocation::Pitlane;
+        ctx.update_car_state(pitting);
+        ctx.update_car_state(car_update(2, 2, 1, 0.6));
+        ctx.update_car_state(car_update(3, 3, 1, 0.6));
+        ctx.update_car_state(car_update(4, 1, 1, 0.6));
+
+        assert_eq!(
+            ctx.update_session(session(SessionPhase::Session)),
+            vec![
+                SessionEvent::PositionChanged {
+                    car_id: 4,
+                    from: 4,
+                    to: 1
+                },
+                SessionEvent::PositionChanged {
+                    car_id: 1,
+                    from: 1,
+                    to: 4
+                },
+                SessionEvent::Overtake {
+                    car_id: 4,
+                    overtaken_car_id: 2
+                },
+                SessionEvent::Overtake {
+                    car_id: 4,
+                    overtaken_car_id: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn no_overtakes_outside_races() {
+        let mut ctx = Context::new();
+        let qualifying = session_update(SessionType::Qualifying, SessionPhase::Session, 1000.0);
+        ctx.update_session(qualifying.clone());
+        for (id, position) in [(1, 1), (2, 2), (3, 3)] {
+            ctx.update_car_state(car_update(id, position, 1, 0.5));
+        }
+        ctx.update_session(qualifying.clone());
+
+        // Car 3 improves its best lap and jumps to the top of the order, wherever it is
+        ctx.update_car_state(car_update(1, 2, 1, 0.6));
+        ctx.update_car_state(car_update(2, 3, 1, 0.6));
+        ctx.update_car_state(car_update(3, 1, 2, 0.0));
+
+        let events = ctx.update_session(qualifying);
+        assert_eq!(events.len(), 3);
+        assert!(events
+            .iter()
+            .all(|e| matches!(e, SessionEvent::PositionChanged { .. })));
+    }
+
+    #[test]
+    fn phase_and_weather_changes() {
+        let mut ctx = Context::new();
+        assert!(ctx
+            .update_session(session(SessionPhase::PreSession))
+            .is_empty());
+
+        let mut update = session(SessionPhase::Session);
+        update.rain_level = 2;
+        assert_eq!(
+            ctx.update_session(update),
+            vec![
+                SessionEvent::SessionPhaseChanged {
+                    from: SessionPhase::PreSession,
+                    to: SessionPhase::Session
+                },
+                SessionEvent::WeatherChanged {
+                    from: Weather {
+                        rain_level: 0,
+                        wetness: 0
+                    },
+                    to: Weather {
+                        rain_level: 2,
+                        wetness: 0
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stopped_cars() {
+        let mut ctx = Context::new();
+        ctx.update_session(session(SessionPhase::Starting));
+        let mut update = car_update(1, 1, 0, 0.0);
+        update.speed_kph = 0;
+        // Cars waiting on the grid haven't stopped
+        ctx.update_car_state(update.clone());
+        assert!(ctx.update_car_state(update.clone()).is_empty());
+
+        ctx.update_session(session(SessionPhase::Session));
+        update.speed_kph = 150;
+        assert!(ctx.update_car_state(update.clone()).is_empty());
+        update.speed_kph = 2;
+        assert_eq!(
+            ctx.update_car_state(update.clone()),
+            vec![SessionEvent::CarStopped { car_id: 1 }]
+        );
+        // Only reported once
+        update.speed_kph = 0;
+        assert!(ctx.update_car_state(update).is_empty());
+    }
+
+    #[test]
+    fn driver_swaps() {
+        let mut ctx = Context::new();
+        let mut update = car_update(1, 1, 10, 0.0);
+        ctx.update_car_state(update.clone());
+        update.driver_index = 1;
+
+        let events = ctx.update_car_state(update);
+        assert_eq!(
+            events[0],
+            SessionEvent::DriverSwap {
+                car_id: 1,
+                from: 0,
+                to: 1
+            }
+        );
+        assert!(matches!(
+            events[1],
+            SessionEvent::StintFinished { car_id: 1, .. }
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+//! Detecting the start of a new session, and keeping the sessions which came before it.
+
+use super::{CarContext, Classification, Context, SessionEvent};
+use crate::protocol::acc_enum::SessionPhase;
+use crate::protocol::inbound::{RealtimeUpdate, TrackData};
+use log::info;
+
+/// The state of a session, kept once the next session has started.
+#[derive(Debug, Clone)]
+pub struct ArchivedSession {
+    /// The last update received during the session.
+    pub session: RealtimeUpdate<'static>,
+    pub track: Option<TrackData<'static>>,
+    /// Every car in the session, in the order of the final standings.
+    pub cars: Vec<(u16, CarContext)>,
+    /// The classification taken when the session ended, or when it was archived if the end of
+    /// the session wasn't seen.
+    pub classification: Classification,
+}
+
+impl ArchivedSession {
+    pub fn car_by_id(&self, id: u16) -> Option<&CarContext> {
+        self.cars
+            .iter()
+            .find_map(|(car_id, car)| if *car_id == id { Some(car) } else { None })
+    }
+}
+
+/// A running session's clock going back by more than this, in milliseconds, means the session
+/// was restarted.
+const RESTART_TIME_JUMP_MS: f32 = 10_000.0;
+
+/// Whether `update` belongs to a different session to `previous`.
+fn is_new_session(previous: &RealtimeUpdate, update: &RealtimeUpdate) -> bool {
+    previous.event_index != update.event_index
+        || previous.session_index != update.session_index
+        || previous.session_type != update.session_type
+        || is_restart(previous, update)
+}
+
+/// A restarted session keeps its indexes and type, but goes back to before the green flag or
+/// has its clock reset.
+fn is_restart(previous: &RealtimeUpdate, update: &RealtimeUpdate) -> bool {
+    let started = |phase: SessionPhase| u8::from(phase) >= u8::from(SessionPhase::Session);
+    let back_to_start = started(previous.session_phase)
+        && matches!(
+            update.session_phase,
+            SessionPhase::Starting
+                | SessionPhase::PreFormation
+                | SessionPhase::FormationLap
+                | SessionPhase::PreSession
+        );
+    let clock_reset = previous.session_phase == SessionPhase::Session
+        && update.session_phase == SessionPhase::Session
+        && previous.session_time - update.session_time > RESTART_TIME_JUMP_MS;
+    back_to_start || clock_reset
+}
+
+impl Context {
+    /// Sessions which have finished or been restarted since the client connected, oldest first.
+    pub fn archived_sessions(&self) -> &[ArchivedSession] {
+        &self.archive
+    }
+
+    /// Archives the current session if `update` starts a new one, and clears everything recorded
+    /// during it apart from the entry list.
+    pub(super) fn check_new_session(
+        &mut self,
+        update: &RealtimeUpdate,
+        events: &mut Vec<SessionEvent>,
+    ) {
+        let previous = match &self.session {
+            Some(previous) if is_new_session(previous, update) => previous.clone(),
+            _ => return,
+        };
+        info!(
+            "Session {} of event {} has finished, starting {:?}",
+            previous.session_index, previous.event_index, update.session_type
+        );
+
+        let cars = self
+            .standings()
+            .into_iter()
+            .map(|s| (s.car_id, s.car.clone()))
+            .collect();
+        let classification = match self.classification.take() {
+            Some(classification) => classification,
+            None => self.build_classification(previous.session_type),
+        };
+        self.archive.push(ArchivedSession {
+            session: previous,
+            track: self.track.clone(),
+            cars,
+            classification,
+        });
+
+        for car in self.cars.values_mut() {
+            *car = CarContext {
+                entry: car.entry.take(),
+                ..CarContext::default()
+            };
+        }
+        self.progress.clear();
+        self.positions.clear();
+        self.laps_at_update.clear();
+        self.finishes.clear();
+        self.sectors = Default::default();
+
+        events.push(SessionEvent::SessionArchived {
+            index: self.archive.len() - 1,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::{CupCategory, SessionPhase, SessionType};
+    use crate::session::fixtures::{car_update, entry, lap, session_update};
+
+    #[test]
+    fn archives_finished_sessions() {
+        let mut ctx = Context::new();
+        ctx.update_car_entry(entry(1, 1, CupCategory::Overall));
+        ctx.update_car_entry(entry(2, 2, CupCategory::Overall));
+
+        let practice = session_update(SessionType::Practice, SessionPhase::Session, 1000.0);
+        assert!(ctx.update_session(practice.clone()).is_empty());
+        ctx.update_car_state(car_update(1, 2, 0, 0.5));
+        ctx.update_car_state(car_update(2, 1, 0, 0.5));
+        let mut update = car_update(1, 2, 1, 0.0);
+        update.last_lap = lap(1, Some(100_000));
+        ctx.update_car_state(update);
+
+        // Updates within the session don't archive it
+        let mut later = practice.clone();
+        later.session_phase = SessionPhase::SessionOver;
+        ctx.update_session(later);
+        assert!(ctx.archived_sessions().is_empty());
+
+        let mut qualifying = session_update(SessionType::Qualifying, SessionPhase::Starting, 0.0);
+        qualifying.session_index = 1;
+        let events = ctx.update_session(qualifying);
+        assert_eq!(events[0], SessionEvent::SessionArchived { index: 0 });
+
+        let archived = &ctx.archived_sessions()[0];
+        assert_eq!(archived.session.session_type, SessionType::Practice);
+        assert_eq!(archived.session.session_phase, SessionPhase::SessionOver);
+        assert_eq!(
+            archived.cars.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_eq!(archived.car_by_id(1).unwrap().laps.len(), 1);
+        assert_eq!(archived.car_by_id(1).unwrap().stints.len(), 1);
+
+        // Entries carry over into the new session, everything else starts again
+        let car = ctx.car_by_id(1).unwrap();
+        assert!(car.entry.is_some());
+        assert!(car.state.is_none());
+        assert!(car.laps.is_empty());
+        assert!(car.stints.is_empty());
+        assert!(ctx.session_best_sectors().theoretical_best().is_none());
+        assert_eq!(
+            ctx.session().map(|s| s.session_type),
+            Some(SessionType::Qualifying)
+        );
+    }
+
+    #[test]
+    fn archives_restarted_sessions() {
+        let mut ctx = Context::new();
+        ctx.update_car_entry(entry(1, 1, CupCategory::Overall));
+
+        let race = |phase, time| session_update(SessionType::Race, phase, time);
+        ctx.update_session(race(SessionPhase::Session, 60_000.0));
+        let mut update = car_update(1, 1, 1, 0.0);
+        update.last_lap = lap(1, Some(100_000));
+        ctx.update_car_state(update);
+
+        // Small steps back in time, or moving on to the end of the session, aren't restarts
+        ctx.update_session(race(SessionPhase::Session, 59_500.0));
+        ctx.update_session(race(SessionPhase::SessionOver, 120_000.0));
+        assert!(ctx.archived_sessions().is_empty());
+
+        // Going back to the grid with the same indexes
+        let events = ctx.update_session(race(SessionPhase::Starting, 0.0));
+        assert_eq!(events[0], SessionEvent::SessionArchived { index: 0 });
+        assert!(ctx.car_by_id(1).unwrap().laps.is_empty());
+
+        // The clock being reset while the session is running
+        ctx.update_session(race(SessionPhase::Session, 300_000.0));
+        ctx.update_car_state(car_update(1, 1, 1, 0.0));
+        let events = ctx.update_session(race(SessionPhase::Session, 1_000.0));
+        assert_eq!(events[0], SessionEvent::SessionArchived { index: 1 });
+        assert_eq!(ctx.archived_sessions().len(), 2);
+        assert_eq!(
+            ctx.archived_sessions()[0].car_by_id(1).unwrap().laps.len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Positions of each car lap by lap, for drawing lap charts.
+
+use super::{CarContext, CarState, Context};
+use crate::protocol::acc_enum::{SessionPhase, SessionType};
+
+/// Where a car was when it completed a lap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LapPosition {
+    /// The number of laps completed, 0 for the starting grid.
+    pub lap: u16,
+    pub position: u16,
+    pub cup_position: u16,
+}
+
+/// One car's line on the lap chart.
+#[derive(Debug, Clone)]
+pub struct LapChartLine<'a> {
+    pub car_id: u16,
+    pub car: &'a CarContext,
+    /// Positions in lap order, starting with the grid when the start was seen.
+    pub positions: &'a [LapPosition],
+}
+
+impl<'a> LapChartLine<'a> {
+    /// The car's position on the starting grid.
+    pub fn grid(&self) -> Option<&'a LapPosition> {
+        self.positions.first().filter(|p| p.lap == 0)
+    }
+
+    /// The car's position after completing `lap` laps.
+    pub fn at_lap(&self, lap: u16) -> Option<&'a LapPosition> {
+        self.positions.iter().find(|p| p.lap == lap)
+    }
+
+    /// Overall positions gained since the start, negative if positions were lost.
+    pub fn positions_gained(&self) -> Option<i32> {
+        let current = self.car.state.as_ref().map(|s| s.position)?;
+        self.grid()
+            .map(|grid| grid.position as i32 - current as i32)
+    }
+
+    /// Positions gained within the car's cup category since the start.
+    pub fn class_positions_gained(&self) -> Option<i32> {
+        let current = self.car.state.as_ref().map(|s| s.cup_position)?;
+        self.grid()
+            .map(|grid| grid.cup_position as i32 - current as i32)
+    }
+}
+
+impl CarContext {
+    /// Records the car's position at the end of a lap, or on the grid before the start.
+    pub(super) fn record_position(
+        &mut self,
+        update: &CarState,
+        lap_completed: bool,
+        on_grid: bool,
+    ) {
+        if update.position == 0 {
+            return;
+        }
+        let position = LapPosition {
+            lap: update.laps,
+            position: update.position,
+            cup_position: update.cup_position,
+        };
+
+        if on_grid && update.laps == 0 {
+            // Grid positions can still be shuffled before the start, so keep the latest
+            match self.lap_positions.first_mut() {
+                Some(grid) if grid.lap == 0 => *grid = position,
+                _ => self.lap_positions.insert(0, position),
+            }
+        } else if lap_completed {
+            self.lap_positions.push(position);
+        }
+    }
+}
+
+impl Context {
+    /// Every car's positions lap by lap, in the order of the current [`standings`](Context::standings).
+    pub fn lap_chart(&self) -> Vec<LapChartLine<'_>> {
+        self.standings()
+            .into_iter()
+            .map(|s| LapChartLine {
+                car_id: s.car_id,
+                car: s.car,
+                positions: &s.car.lap_positions,
+            })
+            .collect()
+    }
+
+    /// Whether a race is waiting to start, with the cars on the grid.
+    pub(super) fn on_grid(&self) -> bool {
+        match &self.session {
+            Some(s) => {
+                s.session_type == SessionType::Race
+                    && matches!(
+                        s.session_phase,
+                        SessionPhase::Starting
+                            | SessionPhase::PreFormation
+                            | SessionPhase::FormationLap
+                            | SessionPhase::PreSession
+                    )
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::CupCategory;
+    use crate::session::fixtures::{car_update, entry, session_update};
+
+    fn race(ctx: &mut Context, phase: SessionPhase) {
+        ctx.update_session(session_update(SessionType::Race, phase, 0.0));
+    }
+
+    #[test]
+    fn positions_per_lap_and_against_the_grid() {
+        let mut ctx = Context::new();
+        ctx.update_car_entry(entry(1, 1, CupCategory::Overall));
+        ctx.update_car_entry(entry(2, 2, CupCategory::Overall));
+
+        race(&mut ctx, SessionPhase::PreFormation);
+        ctx.update_car_state(car_update(1, 2, 0, 0.9));
+        ctx.update_car_state(car_update(2, 1, 0, 0.95));
+        // The grid is taken from the latest update before the start
+        race(&mut ctx, SessionPhase::FormationLap);
+        ctx.update_car_state(car_update(1, 1, 0, 0.9));
+        ctx.update_car_state(car_update(2, 2, 0, 0.95));
+
+        race(&mut ctx, SessionPhase::Session);
+        ctx.update_car_state(car_update(1, 2, 0, 0.5));
+        ctx.update_car_state(car_update(2, 1, 0, 0.6));
+        ctx.update_car_state(car_update(2, 1, 1, 0.01));
+        ctx.update_car_state(car_update(1, 2, 1, 0.0));
+        ctx.update_car_state(car_update(1, 2, 2, 0.0));
+        ctx.update_car_state(car_update(2, 1, 2, 0.0));
+
+        let chart = ctx.lap_chart();
+        assert_eq!(
+            chart.iter().map(|l| l.car_id).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+
+        let leader = &chart[0];
+        assert_eq!(
+            leader
+                .positions
+                .iter()
+                .map(|p| (p.lap, p.position))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (1, 1), (2, 1)]
+        );
+        assert_eq!(leader.grid().map(|g| g.position), Some(2));
+        assert_eq!(leader.at_lap(1).map(|p| p.position), Some(1));
+        assert_eq!(leader.positions_gained(), Some(1));
+        assert_eq!(leader.class_positions_gained(), Some(1));
+        assert_eq!(chart[1].positions_gained(), Some(-1));
+        assert!(chart[1].at_lap(3).is_none());
+    }
+
+    #[test]
+    fn no_grid_outside_races() {
+        let mut ctx = Context::new();
+        ctx.update_session(session_update(
+            SessionType::Practice,
+            SessionPhase::PreSession,
+            0.0,
+        ));
+        ctx.update_car_state(car_update(1, 1, 0, 0.0));
+        ctx.update_car_state(car_update(1, 1, 1, 0.0));
+
+        let chart = ctx.lap_chart();
+        assert!(chart[0].grid().is_none());
+        assert_eq!(chart[0].positions_gained(), None);
+        assert_eq!(chart[0].positions.len(), 1);
+    }
+}
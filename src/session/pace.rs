@@ -0,0 +1,176 @@
+//! Race pace from a car's lap history.
+
+use std::time::Duration;
+
+use super::{CarContext, CompletedLap, Stint};
+
+/// The number of laps in the rolling average shown in the [`standings`](super::Context::standings).
+pub const PACE_WINDOW: usize = 5;
+
+/// A summary of a run of lap times.
+///
+/// Only representative laps are counted, so in laps, out laps, invalid laps and the placeholder
+/// laps sent before a time is set are all left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pace {
+    /// The number of representative laps.
+    pub laps: usize,
+    /// The mean of the most recent laps, up to the requested window.
+    pub rolling_average: Option<Duration>,
+    pub median: Option<Duration>,
+    /// The standard deviation of the lap times, smaller values being more consistent.
+    pub consistency: Option<Duration>,
+    /// The change in lap time per lap in seconds, from a linear fit. Positive as the car slows.
+    pub degradation: Option<f64>,
+}
+
+impl Pace {
+    /// Computes the pace from laps as stored in [`CarContext::laps`], in lap order.
+    pub fn from_laps<'a, I>(laps: I, window: usize) -> Pace
+    where
+        I: IntoIterator<Item = &'a CompletedLap>,
+    {
+        let times: Vec<(f64, f64)> = laps
+            .into_iter()
+            .filter(|c| !(c.lap.is_invalid || c.lap.is_in_lap || c.lap.is_out_lap))
+            .filter_map(|c| c.lap.time().map(|t| (c.number as f64, t.as_secs_f64())))
+            .collect();
+        if times.is_empty() {
+            return Pace::default();
+        }
+
+        let count = times.len() as f64;
+        let recent = &times[times.len().saturating_sub(window.max(1))..];
+        let rolling_average = recent.iter().map(|&(_, t)| t).sum::<f64>() / recent.len() as f64;
+
+        let mut sorted: Vec<f64> = times.iter().map(|&(_, t)| t).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        // The two middle laps are the same one when the count is odd
+        let median = (sorted[(sorted.len() - 1) / 2] + sorted[sorted.len() / 2]) / 2.0;
+
+        let mean = sorted.iter().sum::<f64>() / count;
+        let variance = sorted.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / count;
+
+        // Least squares slope of lap time against lap number
+        let mean_lap = times.iter().map(|&(n, _)| n).sum::<f64>() / count;
+        let spread: f64 = times.iter().map(|&(n, _)| (n - mean_lap).powi(2)).sum();
+        let degradation = if spread > 0.0 {
+            let covariance: f64 = times
+                .iter()
+                .map(|&(n, t)| (n - mean_lap) * (t - mean))
+                .sum();
+            Some(covariance / spread)
+        } else {
+            None
+        };
+
+        Pace {
+            laps: times.len(),
+            rolling_average: Some(Duration::from_secs_f64(rolling_average)),
+            median: Some(Duration::from_secs_f64(median)),
+            consistency: Some(Duration::from_secs_f64(variance.sqrt())),
+            degradation,
+        }
+    }
+}
+
+impl CarContext {
+    /// The car's pace over the whole session, averaging the last `window` laps.
+    pub fn pace(&self, window: usize) -> Pace {
+        Pace::from_laps(&self.laps, window)
+    }
+
+    /// The car's pace during one of its stints.
+    pub fn stint_pace(&self, stint: &Stint, window: usize) -> Pace {
+        let first = stint.start_lap;
+        let last = stint.start_lap + stint.laps;
+        Pace::from_laps(
+            self.laps
+                .iter()
+                .filter(|completed| completed.number > first && completed.number <= last),
+            window,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::inbound::Lap;
+    use crate::session::fixtures::lap;
+
+    fn completed(number: u16, lap: Lap) -> CompletedLap {
+        CompletedLap {
+            number,
+            lap,
+            completed_at: Duration::default(),
+        }
+    }
+
+    fn laps(times: &[i32]) -> Vec<CompletedLap> {
+        times
+            .iter()
+            .enumerate()
+            .map(|(i, &ms)| completed(i as u16 + 1, lap(1, Some(ms))))
+            .collect()
+    }
+
+    fn secs(duration: Option<Duration>) -> f64 {
+        duration.unwrap().as_secs_f64()
+    }
+
+    #[test]
+    fn pace_statistics() {
+        let mut history = laps(&[
+            120_000, 100_000, 101_000, 150_000, 102_000, 103_000, 104_000, 125_000,
+        ]);
+        history[0].lap.is_out_lap = true;
+        history[3].lap.is_invalid = true;
+        history[7].lap.is_in_lap = true;
+        history.push(completed(9, lap(1, None)));
+
+        let pace = Pace::from_laps(&history, 3);
+        assert_eq!(pace.laps, 5);
+        assert!((secs(pace.rolling_average) - 103.0).abs() < 1e-6);
+        assert!((secs(pace.median) - 102.0).abs() < 1e-6);
+        assert!((secs(pace.consistency) - 2.0f64.sqrt()).abs() < 1e-6);
+        // Lap 4 is left out, so the fit is shallower than the second a lap between the others
+        assert!((pace.degradation.unwrap() - 13.0 / 17.2).abs() < 1e-6);
+
+        let even = Pace::from_laps(&laps(&[100_000, 104_000]), 5);
+        assert!((secs(even.median) - 102.0).abs() < 1e-6);
+        assert!((secs(even.rolling_average) - 102.0).abs() < 1e-6);
+        assert!((even.degradation.unwrap() - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn too_few_laps() {
+        assert_eq!(Pace::from_laps(&[], 5), Pace::default());
+
+        let single = Pace::from_laps(&laps(&[100_000]), 5);
+        assert_eq!(single.laps, 1);
+        assert_eq!(single.consistency, Some(Duration::default()));
+        assert_eq!(single.degradation, None);
+    }
+
+    #[test]
+    fn pace_per_stint() {
+        let car = CarContext {
+            laps: laps(&[100_000, 101_000, 102_000, 110_000, 110_500]),
+            ..CarContext::default()
+        };
+        let stint = Stint {
+            driver_index: 1,
+            start_lap: 3,
+            start_time: Duration::default(),
+            laps: 2,
+            duration: Duration::default(),
+        };
+
+        let pace = car.stint_pace(&stint, 5);
+        assert_eq!(pace.laps, 2);
+        assert!((secs(pace.median) - 110.25).abs() < 1e-6);
+        assert_eq!(car.pace(5).laps, 5);
+    }
+}
@@ -0,0 +1,348 @@
+//! Sector times, and the best of them per car, driver, class and session.
+
+use std::time::Duration;
+
+use fnv::FnvHashMap;
+
+use super::{CarContext, CarState, Context, SessionEvent};
+use crate::protocol::acc_enum::CupCategory;
+use crate::protocol::inbound::Lap;
+
+/// The number of timed sectors. The simulator splits every track into three, so this is also
+/// what [`TrackInfo::sectors`](crate::track::TrackInfo::sectors) reports.
+pub const SECTORS: usize = 3;
+
+/// How a newly completed sector compares with the best times so far, following the colours of
+/// a timing screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SectorRating {
+    /// The fastest of the session, shown in purple.
+    SessionBest,
+    /// The fastest within the car's cup category.
+    ClassBest,
+    /// The driver's own best, shown in green.
+    PersonalBest,
+    /// Slower than the driver's best, or set on an invalid lap, shown in yellow.
+    Slower,
+}
+
+/// The best time set in each sector.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SectorTimes([Option<Duration>; SECTORS]);
+
+impl SectorTimes {
+    /// The best time in `sector`, counting from 0.
+    pub fn get(&self, sector: usize) -> Option<Duration> {
+        self.0.get(sector).copied().flatten()
+    }
+
+    /// The sum of the best sectors, once a time has been set in each of them.
+    pub fn theoretical_best(&self) -> Option<Duration> {
+        self.0.iter().copied().sum()
+    }
+
+    fn is_beaten_by(&self, sector: usize, time: Duration) -> bool {
+        match self.get(sector) {
+            Some(best) => time < best,
+            None => true,
+        }
+    }
+
+    fn improve(&mut self, sector: usize, time: Duration) {
+        if self.is_beaten_by(sector, time) {
+            self.0[sector] = Some(time);
+        }
+    }
+}
+
+/// Best sectors across the session, and the progress of each car through its current lap.
+#[derive(Debug, Default)]
+pub(crate) struct SectorTracker {
+    session: SectorTimes,
+    classes: FnvHashMap<CupCategory, SectorTimes>,
+    drivers: FnvHashMap<(u16, u16), SectorTimes>,
+    /// The number of sectors of each car's current lap which have already been recorded.
+    completed: FnvHashMap<u16, usize>,
+}
+
+impl SectorTracker {
+    pub(crate) fn retain_cars(&mut self, car_ids: &[u16]) {
+        self.completed.retain(|k, _| car_ids.contains(k));
+        self.drivers.retain(|(k, _), _| car_ids.contains(k));
+    }
+}
+
+/// The valid sector times of a lap, with the index of each sector.
+fn sector_times(lap: &Lap) -> impl Iterator<Item = (usize, Duration)> + '_ {
+    lap.splits
+        .iter()
+        .enumerate()
+        .filter(|&(_, &ms)| ms > 0 && ms != i32::MAX)
+        .map(|(sector, &ms)| (sector, Duration::from_millis(ms as u64)))
+}
+
+impl CarContext {
+    /// The sum of the car's best sectors, across all of its drivers.
+    pub fn theoretical_best(&self) -> Option<Duration> {
+        self.best_sectors.theoretical_best()
+    }
+}
+
+impl Context {
+    /// The fastest time set in each sector by any car.
+    pub fn session_best_sectors(&self) -> &SectorTimes {
+        &self.sectors.session
+    }
+
+    /// The fastest time set in each sector by the cars in one cup category.
+    pub fn class_best_sectors(&self, cup_category: CupCategory) -> Option<&SectorTimes> {
+        self.sectors.classes.get(&cup_category)
+    }
+
+    /// The fastest time set in each sector by one driver of a car.
+    pub fn driver_best_sectors(&self, car_id: u16, driver_index: u16) -> Option<&SectorTimes> {
+        self.sectors.drivers.get(&(car_id, driver_index))
+    }
+
+    /// Records the sectors completed since the car's previous update.
+    ///
+    /// Sectors are reported as soon as the simulator sends their times in the current lap, or
+    /// otherwise once the lap is complete.
+    pub(super) fn track_sectors(&mut self, update: &CarState, events: &mut Vec<SessionEvent>) {
+        let car = self.cars.get(&update.id);
+        let class = car.and_then(|c| c.entry.as_ref()).map(|e| e.cup_category);
+        let previous_laps = car.and_then(|c| c.state.as_ref()).map(|s| s.laps);
+        let mut seen = self.sectors.completed.get(&update.id).copied().unwrap_or(0);
+
+        let previous_laps = match previous_laps {
+            Some(laps) => laps,
+            None => {
+                // Connecting mid-session, so take in the times already set without reporting them
+                for lap in &[update.best_session_lap, update.last_lap] {
+                    for (sector, time) in sector_times(lap) {
+                        self.record_sector(update.id, lap, class, sector, time);
+                    }
+                }
+                self.sectors
+                    .completed
+                    .insert(update.id, sector_times(&update.current_lap).count());
+                return;
+            }
+        };
+
+        let mut completed = vec![];
+        if update.laps > previous_laps {
+            completed.extend(
+                sector_times(&update.last_lap)
+                    .filter(|&(sector, _)| sector >= seen)
+                    .map(|(sector, time)| (&update.last_lap, sector, time)),
+            );
+            seen = 0;
+        }
+        completed.extend(
+            sector_times(&update.current_lap)
+                .filter(|&(sector, _)| sector >= seen)
+                .map(|(sector, time)| (&update.current_lap, sector, time)),
+        );
+        // Placeholder splits are sent for sectors without a time yet, so only count timed ones
+        self.sectors.completed.insert(
+            update.id,
+            seen.max(sector_times(&update.current_lap).count()),
+        );
+
+        for (lap, sector, time) in completed {
+            let rating = self.record_sector(update.id, lap, class, sector, time);
+            events.push(SessionEvent::SectorCompleted {
+                car_id: update.id,
+                sector,
+                time,
+                rating,
+            });
+        }
+    }
+
+    fn record_sector(
+        &mut self,
+        car_id: u16,
+        lap: &Lap,
+        class: Option<CupCategory>,
+        sector: usize,
+        time: Duration,
+    ) -> SectorRating {
+        if lap.is_invalid || sector >= SECTORS {
+            return SectorRating::Slower;
+        }
+
+        let tracker = &mut self.sectors;
+        let driver = (car_id, lap.driver_index);
+        let beats =
+            |best: Option<&SectorTimes>| best.map(|b| b.is_beaten_by(sector, time)).unwrap_or(true);
+        let rating = if beats(Some(&tracker.session)) {
+            SectorRating::SessionBest
+        } else if class.is_some() && beats(class.and_then(|c| tracker.classes.get(&c))) {
+            SectorRating::ClassBest
+        } else if beats(tracker.drivers.get(&driver)) {
+            SectorRating::PersonalBest
+        } else {
+            SectorRating::Slower
+        };
+
+        tracker.session.improve(sector, time);
+        if let Some(class) = class {
+            tracker
+                .classes
+                .entry(class)
+                .or_default()
+                .improve(sector, time);
+        }
+        tracker
+            .drivers
+            .entry(driver)
+            .or_default()
+            .improve(sector, time);
+        self.cars
+            .entry(car_id)
+            .or_default()
+            .best_sectors
+            .improve(sector, time);
+
+        rating
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::fixtures::{car_update, entry, lap};
+    use tinyvec::ArrayVec;
+
+    fn timed_lap(car_id: u16, splits: &[i32]) -> Lap {
+        let mut lap = lap(car_id, Some(splits.iter().sum()));
+        lap.splits = splits.iter().copied().collect::<ArrayVec<[i32; 3]>>();
+        lap
+    }
+
+    fn ratings(events: &[SessionEvent]) -> Vec<(usize, SectorRating)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                SessionEvent::SectorCompleted { sector, rating, .. } => Some((*sector, *rating)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn context() -> Context {
+        let mut ctx = Context::new();
+        ctx.update_car_entry(entry(1, 1, CupCategory::Overall));
+        ctx.update_car_entry(entry(2, 2, CupCategory::ProAm));
+        ctx.update_car_state(car_update(1, 1, 0, 0.9));
+        ctx.update_car_state(car_update(2, 2, 0, 0.8));
+        ctx
+    }
+
+    #[test]
+    fn best_sectors_and_ratings() {
+        let mut ctx = context();
+
+        let mut update = car_update(1, 1, 1, 0.0);
+        update.last_lap = timed_lap(1, &[30_000, 40_000, 35_000]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![
+                (0, SectorRating::SessionBest),
+                (1, SectorRating::SessionBest),
+                (2, SectorRating::SessionBest)
+            ]
+        );
+
+        let mut update = car_update(2, 2, 1, 0.0);
+        update.last_lap = timed_lap(2, &[30_500, 39_000, 36_000]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![
+                (0, SectorRating::ClassBest),
+                (1, SectorRating::SessionBest),
+                (2, SectorRating::ClassBest)
+            ]
+        );
+
+        // A sector reported live isn't reported again when the lap completes
+        let mut update = car_update(1, 1, 1, 0.4);
+        update.current_lap = timed_lap(1, &[29_000]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![(0, SectorRating::SessionBest)]
+        );
+        let mut update = car_update(1, 1, 2, 0.0);
+        update.last_lap = timed_lap(1, &[29_000, 41_000, 35_000]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![(1, SectorRating::Slower), (2, SectorRating::Slower)]
+        );
+
+        // Placeholders for sectors not yet timed don't count as completed
+        let mut update = car_update(1, 1, 2, 0.4);
+        update.current_lap = lap(1, None);
+        update.current_lap.splits = ArrayVec::from([29_500, i32::MAX, i32::MAX]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![(0, SectorRating::Slower)]
+        );
+        let mut update = car_update(1, 1, 3, 0.0);
+        update.last_lap = timed_lap(1, &[29_500, 42_000, 36_000]);
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![(1, SectorRating::Slower), (2, SectorRating::Slower)]
+        );
+
+        let car = ctx.car_by_id(1).unwrap();
+        assert_eq!(car.theoretical_best(), Some(Duration::from_millis(104_000)));
+        let session = ctx.session_best_sectors();
+        assert_eq!(session.get(1), Some(Duration::from_millis(39_000)));
+        assert_eq!(
+            session.theoretical_best(),
+            Some(Duration::from_millis(103_000))
+        );
+        let pro_am = ctx.class_best_sectors(CupCategory::ProAm).unwrap();
+        assert_eq!(pro_am.get(0), Some(Duration::from_millis(30_500)));
+        let driver = ctx.driver_best_sectors(1, 0).unwrap();
+        assert_eq!(driver.get(0), Some(Duration::from_millis(29_000)));
+        assert!(ctx.driver_best_sectors(1, 1).is_none());
+    }
+
+    #[test]
+    fn invalid_laps_are_not_bests() {
+        let mut ctx = context();
+
+        let mut update = car_update(2, 2, 1, 0.0);
+        update.last_lap = timed_lap(2, &[30_000, 40_000, 35_000]);
+        update.last_lap.is_invalid = true;
+        assert_eq!(
+            ratings(&ctx.update_car_state(update)),
+            vec![
+                (0, SectorRating::Slower),
+                (1, SectorRating::Slower),
+                (2, SectorRating::Slower)
+            ]
+        );
+        assert_eq!(ctx.session_best_sectors(), &SectorTimes::default());
+        assert_eq!(ctx.car_by_id(2).unwrap().theoretical_best(), None);
+    }
+
+    #[test]
+    fn existing_times_are_taken_in_silently() {
+        let mut ctx = Context::new();
+        let mut update = car_update(1, 1, 5, 0.5);
+        update.best_session_lap = timed_lap(1, &[30_000, 40_000, 35_000]);
+        update.last_lap = timed_lap(1, &[31_000, 39_500, 36_000]);
+
+        assert!(ratings(&ctx.update_car_state(update)).is_empty());
+        assert_eq!(
+            ctx.car_by_id(1).unwrap().theoretical_best(),
+            Some(Duration::from_millis(104_500))
+        );
+    }
+}
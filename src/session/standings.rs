@@ -0,0 +1,228 @@
+//! The leaderboard view of a session.
+
+use std::time::Duration;
+
+use super::{CarContext, Context, Gap, Pace, PACE_WINDOW};
+use crate::protocol::acc_enum::{CarLocation, CarModel, CupCategory};
+use crate::protocol::inbound::Driver;
+
+/// A car's place in the session, ordered by its overall position.
+///
+/// Fields taken from the entry list or the latest car update are `None` until that information
+/// has been received.
+#[derive(Debug, Clone)]
+pub struct Standing<'a> {
+    pub car_id: u16,
+    pub car: &'a CarContext,
+    /// Position in the sorted standings, starting from 1.
+    pub position: usize,
+    /// Position within the car's cup category, as reported by the simulator.
+    pub cup_position: Option<u16>,
+    pub race_number: Option<i32>,
+    pub team_name: Option<&'a str>,
+    pub driver: Option<&'a Driver<'a>>,
+    pub model: Option<CarModel>,
+    pub cup_category: Option<CupCategory>,
+    /// The number of completed laps, 0 until the car has been seen on track.
+    pub laps: u16,
+    pub best_lap: Option<Duration>,
+    pub last_lap: Option<Duration>,
+    pub location: Option<CarLocation>,
+    /// Live delta to the driver's best lap, in milliseconds.
+    pub delta: Option<i32>,
+    /// Pace over the session, with a rolling average of [`PACE_WINDOW`] laps.
+    pub pace: Pace,
+    /// Pace during the current stint, with a rolling average of [`PACE_WINDOW`] laps.
+    pub stint_pace: Pace,
+    /// `None` for the leader.
+    pub gap_to_leader: Option<Gap>,
+    /// Gap to the car one place ahead, `None` for the leader.
+    pub interval: Option<Gap>,
+    /// Gap to the leading car with the same [`CupCategory`], `None` for the class leader or when
+    /// the car has no entry.
+    pub gap_to_class_leader: Option<Gap>,
+}
+
+impl<'a> Standing<'a> {
+    fn new(car_id: u16, car: &'a CarContext, position: usize) -> Standing<'a> {
+        let entry = car.entry.as_ref();
+        let state = car.state.as_ref();
+        Standing {
+            car_id,
+            car,
+            position,
+            cup_position: state.map(|s| s.cup_position),
+            race_number: entry.map(|e| e.race_number),
+            team_name: entry.map(|e| e.team_name.as_ref()),
+            driver: car.current_driver(),
+            model: entry.map(|e| e.model),
+            cup_category: entry.map(|e| e.cup_category),
+            laps: state.map_or(0, |s| s.laps),
+            best_lap: state.and_then(|s| s.best_session_lap.time()),
+            last_lap: state.and_then(|s| s.last_lap.time()),
+            location: state.map(|s| s.car_location),
+            delta: state.map(|s| s.delta),
+            pace: car.pace(PACE_WINDOW),
+            stint_pace: car
+                .current_stint()
+                .map(|stint| car.stint_pace(stint, PACE_WINDOW))
+                .unwrap_or_default(),
+            gap_to_leader: None,
+            interval: None,
+            gap_to_class_leader: None,
+        }
+    }
+
+    /// Whether the car is anywhere in the pit lane, including the entry and exit.
+    pub fn in_pits(&self) -> bool {
+        matches!(self.location, Some(location) if location.in_pits())
+    }
+}
+
+impl Context {
+    /// All cars in the session, sorted by position, with the gaps between them.
+    ///
+    /// Cars which haven't been given a position yet are placed at the end, in order of car ID.
+    pub fn standings(&self) -> Vec<Standing<'_>> {
+        let mut cars: Vec<(u16, &CarContext)> = self.cars.iter().map(|(&k, v)| (k, v)).collect();
+        cars.sort_by_key(|(id, car)| {
+            let position = car.state.as_ref().map_or(0, |s| s.position);
+            (position == 0, position, *id)
+        });
+
+        let mut standings: Vec<Standing<'_>> = Vec::with_capacity(cars.len());
+        for (index, &(car_id, car)) in cars.iter().enumerate() {
+            let mut standing = Standing::new(car_id, car, index + 1);
+            if index > 0 {
+                standing.gap_to_leader = Some(self.gap(cars[0], (car_id, car)));
+                standing.interval = Some(self.gap(cars[index - 1], (car_id, car)));
+            }
+            standing.gap_to_class_leader = standing
+                .cup_category
+                .and_then(|class| standings.iter().find(|s| s.cup_category == Some(class)))
+                .map(|leader| self.gap((leader.car_id, leader.car), (car_id, car)));
+
+            standings.push(standing);
+        }
+
+        standings
+    }
+
+    /// The [`standings`](Context::standings) of only the cars in one cup category.
+    ///
+    /// Positions and gaps are still those of the overall standings.
+    pub fn class_standings(&self, cup_category: CupCategory) -> Vec<Standing<'_>> {
+        self.standings()
+            .into_iter()
+            .filter(|s| s.cup_category == Some(cup_category))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::inbound::EntrylistUpdate;
+    use crate::session::fixtures::{car_update, entry, lap};
+
+    fn context() -> Context {
+        let mut ctx = Context::new();
+        ctx.seed_entrylist(&EntrylistUpdate {
+            connection_id: 1,
+            car_ids: vec![1, 2, 3],
+        });
+        ctx.update_car_entry(entry(1, 7, CupCategory::Overall));
+        ctx.update_car_entry(entry(2, 22, CupCategory::ProAm));
+        ctx.update_car_entry(entry(3, 99, CupCategory::Overall));
+        ctx
+    }
+
+    #[test]
+    fn leaderboard_fields() {
+        let mut ctx = context();
+        let mut update = car_update(2, 1, 4, 0.5);
+        update.cup_position = 1;
+        update.delta = -250;
+        update.best_session_lap = lap(2, Some(101_000));
+        update.last_lap = lap(2, Some(102_500));
+        ctx.update_car_state(update);
+
+        let mut update = car_update(1, 2, 4, 0.4);
+        update.car_location = CarLocation::PitEntry;
+        ctx.update_car_state(update);
+
+        let standings = ctx.standings();
+        let leader = &standings[0];
+        assert_eq!(leader.car_id, 2);
+        assert_eq!(leader.position, 1);
+        assert_eq!(leader.cup_position, Some(1));
+        assert_eq!(leader.race_number, Some(22));
+        assert_eq!(leader.team_name, Some("Team 22"));
+        assert_eq!(
+            leader.driver.map(|d| d.last_name.as_ref()),
+            Some("Smith 22")
+        );
+        assert_eq!(leader.model, Some(CarModel::Ferrari488Evo));
+        assert_eq!(leader.cup_category, Some(CupCategory::ProAm));
+        assert_eq!(leader.laps, 4);
+        assert_eq!(leader.best_lap, Some(Duration::from_millis(101_000)));
+        assert_eq!(leader.last_lap, Some(Duration::from_millis(102_500)));
+        assert_eq!(leader.delta, Some(-250));
+        assert!(!leader.in_pits());
+
+        assert_eq!(standings[1].car_id, 1);
+        assert!(standings[1].in_pits());
+        assert_eq!(standings[1].best_lap, None);
+    }
+
+    #[test]
+    fn missing_entries_and_states() {
+        let mut ctx = context();
+        // A car which isn't in the entry list yet
+        ctx.update_car_state(car_update(4, 1, 0, 0.1));
+        // A car whose current driver hasn't been sent
+        let mut broken = entry(3, 99, CupCategory::Overall);
+        broken.current_driver_index = 3;
+        ctx.update_car_entry(broken);
+
+        let standings = ctx.standings();
+        assert_eq!(
+            standings.iter().map(|s| s.car_id).collect::<Vec<_>>(),
+            vec![4, 1, 2, 3]
+        );
+
+        let unlisted = &standings[0];
+        assert_eq!(unlisted.race_number, None);
+        assert!(unlisted.driver.is_none());
+        assert!(unlisted.gap_to_class_leader.is_none());
+
+        let waiting = &standings[1];
+        assert_eq!(waiting.race_number, Some(7));
+        assert_eq!(waiting.laps, 0);
+        assert_eq!(waiting.location, None);
+        assert!(!waiting.in_pits());
+
+        assert!(standings[3].driver.is_none());
+    }
+
+    #[test]
+    fn filter_by_class() {
+        let mut ctx = context();
+        ctx.update_car_state(car_update(1, 2, 1, 0.0));
+        ctx.update_car_state(car_update(2, 1, 1, 0.0));
+        ctx.update_car_state(car_update(3, 3, 1, 0.0));
+
+        let overall = ctx.class_standings(CupCategory::Overall);
+        assert_eq!(
+            overall
+                .iter()
+                .map(|s| (s.car_id, s.position))
+                .collect::<Vec<_>>(),
+            vec![(1, 2), (3, 3)]
+        );
+        assert!(overall[0].gap_to_class_leader.is_none());
+        assert!(overall[1].gap_to_class_leader.is_some());
+
+        assert!(ctx.class_standings(CupCategory::Am).is_empty());
+    }
+}
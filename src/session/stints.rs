@@ -0,0 +1,253 @@
+//! Pit stop and driver stint tracking.
+
+use std::time::Duration;
+
+use super::{CarContext, CarState, SessionEvent};
+use crate::protocol::acc_enum::CarLocation;
+
+/// A visit to the pit lane.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PitStop {
+    /// The number of laps the car had completed when it entered the pit lane.
+    pub lap: u16,
+    /// Session time at pit entry.
+    pub entry_time: Duration,
+    /// Session time at pit exit, `None` while the car is still in the pit lane.
+    pub exit_time: Option<Duration>,
+}
+
+impl PitStop {
+    /// Time spent in the pit lane, once the car has left it.
+    pub fn duration(&self) -> Option<Duration> {
+        self.exit_time
+            .map(|exit| exit.saturating_sub(self.entry_time))
+    }
+}
+
+/// A continuous period in the car for one driver.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stint {
+    /// The index of the driver within the car's entry.
+    pub driver_index: u16,
+    /// The number of laps the car had completed when the stint started.
+    pub start_lap: u16,
+    /// Session time at the start of the stint.
+    pub start_time: Duration,
+    /// Laps completed during the stint so far.
+    pub laps: u16,
+    /// Length of the stint so far.
+    pub duration: Duration,
+}
+
+impl Stint {
+    fn new(state: &CarState, session_time: Duration) -> Stint {
+        Stint {
+            driver_index: state.driver_index,
+            start_lap: state.laps,
+            start_time: session_time,
+            laps: 0,
+            duration: Duration::default(),
+        }
+    }
+}
+
+impl CarContext {
+    /// The stint of the driver currently in the car.
+    pub fn current_stint(&self) -> Option<&Stint> {
+        self.stints.last()
+    }
+
+    /// The number of times the car has entered the pit lane.
+    pub fn pit_count(&self) -> usize {
+        self.pit_stops.len()
+    }
+
+    /// The total time a driver has spent in the car, across all of their stints.
+    pub fn driver_time(&self, driver_index: u16) -> Duration {
+        self.stints
+            .iter()
+            .filter(|s| s.driver_index == driver_index)
+            .map(|s| s.duration)
+            .sum()
+    }
+
+    /// Compares a new update against the previous state, recording pit stops and stints.
+    pub(crate) fn track_stints(
+        &mut self,
+        update: &CarState,
+        session_time: Duration,
+        events: &mut Vec<SessionEvent>,
+    ) {
+        // Cars are sometimes reported with no location, which shouldn't count as a transition, so
+        // compare against the last location that was reported
+        let was_in_pits = self.last_location.map(CarLocation::in_pits);
+        let in_pits = match update.car_location {
+            CarLocation::None => None,
+            location => {
+                self.last_location = Some(location);
+                Some(location.in_pits())
+            }
+        };
+
+        match (was_in_pits, in_pits) {
+            (Some(false), Some(true)) => {
+                self.pit_stops.push(PitStop {
+                    lap: update.laps,
+                    entry_time: session_time,
+                    exit_time: None,
+                });
+                events.push(SessionEvent::PitEntry {
+                    car_id: update.id,
+                    lap: update.laps,
+                });
+            }
+            (Some(true), Some(false)) => {
+                // Cars leaving the garage were never seen entering the pit lane
+                if let Some(stop) = self.pit_stops.last_mut().filter(|s| s.exit_time.is_none()) {
+                    stop.exit_time = Some(session_time);
+                    events.push(SessionEvent::PitExit {
+                        car_id: update.id,
+                        stop: stop.clone(),
+                    });
+                }
+            }
+            _ => (),
+        }
+
+        match self.stints.last_mut() {
+            Some(stint) if stint.driver_index == update.driver_index => {
+                stint.laps = update.laps.saturating_sub(stint.start_lap);
+                stint.duration = session_time.saturating_sub(stint.start_time);
+            }
+            Some(stint) => {
+                stint.duration = session_time.saturating_sub(stint.start_time);
+                events.push(SessionEvent::StintFinished {
+                    car_id: update.id,
+                    stint: stint.clone(),
+                });
+                self.stints.push(Stint::new(update, session_time));
+            }
+            None => self.stints.push(Stint::new(update, session_time)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::{SessionPhase, SessionType};
+    use crate::session::fixtures::{car_update, session_update};
+    use crate::session::Context;
+
+    /// Sends a car update at `seconds` into the session, returning the derived events.
+    fn update_at(
+        ctx: &mut Context,
+        seconds: u64,
+        laps: u16,
+        driver_index: u16,
+        location: CarLocation,
+    ) -> Vec<SessionEvent> {
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::Session,
+            (seconds * 1000) as f32,
+        ));
+        let mut update = car_update(7, 1, laps, 0.5);
+        update.driver_index = driver_index;
+        update.car_location = location;
+        ctx.update_car_state(update)
+    }
+
+    #[test]
+    fn pit_stops_and_stints() {
+        let mut ctx = Context::new();
+        assert!(update_at(&mut ctx, 0, 0, 0, CarLocation::Track).is_empty());
+        update_at(&mut ctx, 600, 5, 0, CarLocation::Track);
+
+        let events = update_at(&mut ctx, 610, 5, 0, CarLocation::PitEntry);
+        assert_eq!(events, vec![SessionEvent::PitEntry { car_id: 7, lap: 5 }]);
+        update_at(&mut ctx, 620, 5, 0, CarLocation::Pitlane);
+
+        // The driver swap ends the first stint
+        let events = update_at(&mut ctx, 640, 5, 1, CarLocation::Pitlane);
+        assert_eq!(
+            events[1],
+            SessionEvent::StintFinished {
+                car_id: 7,
+                stint: Stint {
+                    driver_index: 0,
+                    start_lap: 0,
+                    start_time: Duration::from_secs(0),
+                    laps: 5,
+                    duration: Duration::from_secs(640),
+                },
+            }
+        );
+
+        update_at(&mut ctx, 665, 5, 1, CarLocation::PitExit);
+        let events = update_at(&mut ctx, 670, 5, 1, CarLocation::Track);
+        let stop = PitStop {
+            lap: 5,
+            entry_time: Duration::from_secs(610),
+            exit_time: Some(Duration::from_secs(670)),
+        };
+        assert_eq!(stop.duration(), Some(Duration::from_secs(60)));
+        assert_eq!(
+            events,
+            vec![SessionEvent::PitExit {
+                car_id: 7,
+                stop: stop.clone()
+            }]
+        );
+
+        update_at(&mut ctx, 1000, 9, 1, CarLocation::Track);
+        let car = ctx.car_by_id(7).unwrap();
+        assert_eq!(car.pit_count(), 1);
+        assert_eq!(car.pit_stops, vec![stop]);
+        assert_eq!(car.stints.len(), 2);
+        let current = car.current_stint().unwrap();
+        assert_eq!((current.driver_index, current.laps), (1, 4));
+        assert_eq!(car.driver_time(0), Duration::from_secs(640));
+        assert_eq!(car.driver_time(1), Duration::from_secs(360));
+    }
+
+    #[test]
+    fn leaving_the_garage_is_not_a_pit_stop() {
+        let mut ctx = Context::new();
+        update_at(&mut ctx, 0, 0, 0, CarLocation::Pitlane);
+        // No location is reported while the car is being teleported
+        update_at(&mut ctx, 5, 0, 0, CarLocation::None);
+        assert!(update_at(&mut ctx, 10, 0, 0, CarLocation::PitExit).is_empty());
+        assert!(update_at(&mut ctx, 15, 0, 0, CarLocation::Track).is_empty());
+
+        let car = ctx.car_by_id(7).unwrap();
+        assert_eq!(car.pit_count(), 0);
+    }
+
+    #[test]
+    fn pit_transitions_across_missing_locations() {
+        let mut ctx = Context::new();
+        update_at(&mut ctx, 0, 3, 0, CarLocation::Track);
+        assert!(update_at(&mut ctx, 5, 3, 0, CarLocation::None).is_empty());
+        let events = update_at(&mut ctx, 10, 3, 0, CarLocation::Pitlane);
+        assert_eq!(events, vec![SessionEvent::PitEntry { car_id: 7, lap: 3 }]);
+
+        assert!(update_at(&mut ctx, 40, 3, 0, CarLocation::None).is_empty());
+        let events = update_at(&mut ctx, 45, 3, 0, CarLocation::Track);
+        let stop = PitStop {
+            lap: 3,
+            entry_time: Duration::from_secs(10),
+            exit_time: Some(Duration::from_secs(45)),
+        };
+        assert_eq!(
+            events,
+            vec![SessionEvent::PitExit {
+                car_id: 7,
+                stop: stop.clone()
+            }]
+        );
+        assert_eq!(ctx.car_by_id(7).unwrap().pit_stops, vec![stop]);
+    }
+}
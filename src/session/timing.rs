@@ -0,0 +1,306 @@
+//! Gap and interval calculations for the cars in a session.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::{CarContext, CarState, Context};
+use crate::protocol::acc_enum::SessionType;
+
+/// How much of a car's recent progress is kept for looking up gaps, in laps.
+const HISTORY_LAPS: f64 = 1.1;
+
+/// The distance between two cars, in time and in laps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gap {
+    /// `None` until enough history has been recorded to estimate the gap.
+    pub time: Option<Duration>,
+    /// Distance around the track, including the fraction of a lap.
+    pub laps: f64,
+}
+
+impl Gap {
+    /// The number of complete laps between the two cars.
+    pub fn whole_laps(&self) -> u32 {
+        self.laps.max(0.0).floor() as u32
+    }
+}
+
+/// The session time at which a car reached each point of its last lap or so.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProgressHistory {
+    /// Pairs of session time in milliseconds and laps completed, including the spline position.
+    samples: VecDeque<(f32, f64)>,
+}
+
+impl ProgressHistory {
+    pub(crate) fn record(&mut self, session_time: f32, state: &CarState) {
+        let mut progress = state.laps as f64 + state.spline_position as f64;
+
+        if let Some(&(time, last)) = self.samples.back() {
+            if session_time < time {
+                // A new session, or a replay being rewound
+                self.samples.clear();
+            } else {
+                // The lap count and spline position don't always wrap in the same update
+                let change = progress - last;
+                if change > 0.5 {
+                    progress -= 1.0;
+                } else if change < -0.5 {
+                    progress += 1.0;
+                }
+
+                if session_time == time {
+                    self.samples.pop_back();
+                }
+            }
+        }
+        self.samples.push_back((session_time, progress));
+
+        // Keep one sample older than the cutoff so the oldest point can still be interpolated
+        let cutoff = progress - HISTORY_LAPS;
+        while self.samples.len() > 2 && self.samples[1].1 < cutoff {
+            self.samples.pop_front();
+        }
+    }
+
+    pub(crate) fn latest(&self) -> Option<(f32, f64)> {
+        self.samples.back().copied()
+    }
+
+    /// The session time at which the car reached `progress`, if it is still in the history.
+    pub(crate) fn time_at(&self, progress: f64) -> Option<f32> {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .find(|(a, b)| a.1 <= progress && progress <= b.1)
+            .map(|(&(t0, p0), &(t1, p1))| {
+                if p1 > p0 {
+                    t0 + ((t1 - t0) as f64 * (progress - p0) / (p1 - p0)) as f32
+                } else {
+                    t1
+                }
+            })
+    }
+}
+
+impl Context {
+    pub(super) fn gap(&self, ahead: (u16, &CarContext), behind: (u16, &CarContext)) -> Gap {
+        let session_type = self.session.as_ref().map(|s| s.session_type);
+
+        if session_type == Some(SessionType::Race) {
+            self.race_gap(ahead, behind)
+        } else {
+            // Outside of a race, cars are ranked by their best lap
+            let best =
+                |car: &CarContext| car.state.as_ref().and_then(|s| s.best_session_lap.time());
+            Gap {
+                time: best(ahead.1)
+                    .zip(best(behind.1))
+                    .and_then(|(ahead, behind)| behind.checked_sub(ahead)),
+                laps: 0.0,
+            }
+        }
+    }
+
+    fn race_gap(&self, ahead: (u16, &CarContext), behind: (u16, &CarContext)) -> Gap {
+        let progress = |(id, car): (u16, &CarContext)| {
+            self.progress
+                .get(&id)
+                .and_then(ProgressHistory::latest)
+                .or_else(|| {
+                    car.state
+                        .as_ref()
+                        .map(|s| (0.0, s.laps as f64 + s.spline_position as f64))
+                })
+        };
+
+        let (ahead_progress, behind_progress) = match (progress(ahead), progress(behind)) {
+            (Some((_, a)), Some(b)) => (a, b),
+            _ => {
+                return Gap {
+                    time: None,
+                    laps: 0.0,
+                }
+            }
+        };
+        let laps = (ahead_progress - behind_progress.1).max(0.0);
+
+        // When the car ahead passed the point the car behind has reached now
+        let time = self
+            .progress
+            .get(&ahead.0)
+            .and_then(|h| h.time_at(behind_progress.1))
+            .and_then(|passed| {
+                let elapsed = behind_progress.0 - passed;
+                if elapsed >= 0.0 {
+                    Some(Duration::from_secs_f64(elapsed as f64 / 1000.0))
+                } else {
+                    None
+                }
+            })
+            // Too far behind for the history, so estimate from the car ahead's lap time
+            .or_else(|| {
+                let state = ahead.1.state.as_ref()?;
+                let lap = state
+                    .last_lap
+                    .time()
+                    .or_else(|| state.best_session_lap.time())?;
+                Some(lap.mul_f64(laps))
+            });
+
+        Gap { time, laps }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::acc_enum::{CupCategory, SessionPhase};
+    use crate::protocol::inbound::EntrylistUpdate;
+    use crate::session::fixtures::{car_update, entry, lap, session_update};
+
+    fn race_context(cars: &[(u16, CupCategory)]) -> Context {
+        let mut ctx = Context::new();
+        ctx.seed_entrylist(&EntrylistUpdate {
+            connection_id: 1,
+            car_ids: cars.iter().map(|&(id, _)| id).collect(),
+        });
+        for &(id, cup) in cars {
+            ctx.update_car_entry(entry(id, id as i32, cup));
+        }
+        ctx
+    }
+
+    /// Sends a session update followed by car updates of `(id, position, laps, spline)`.
+    fn tick(ctx: &mut Context, session_time: f32, cars: &[(u16, u16, u16, f32)]) {
+        ctx.update_session(session_update(
+            SessionType::Race,
+            SessionPhase::Session,
+            session_time,
+        ));
+        for &(id, position, laps, spline) in cars {
+            ctx.update_car_state(car_update(id, position, laps, spline));
+        }
+    }
+
+    #[test]
+    fn history_handles_wrapping_laps() {
+        let mut history = ProgressHistory::default();
+        history.record(1000.0, &car_update(1, 1, 2, 0.9));
+        // The lap count has incremented before the spline position wrapped
+        history.record(2000.0, &car_update(1, 1, 3, 0.95));
+        history.record(3000.0, &car_update(1, 1, 3, 0.05));
+        history.record(4000.0, &car_update(1, 1, 3, 0.5));
+        history.record(5000.0, &car_update(1, 1, 3, 0.98));
+        // The spline position has wrapped before the lap count
+        history.record(6000.0, &car_update(1, 1, 3, 0.02));
+        history.record(7000.0, &car_update(1, 1, 4, 0.1));
+
+        let progress: Vec<f64> = history.samples.iter().map(|s| s.1).collect();
+        assert!(progress.windows(2).all(|w| w[0] < w[1]), "{:?}", progress);
+        assert_eq!(history.time_at(3.275).map(f32::round), Some(3500.0));
+        assert_eq!(history.time_at(5.0), None);
+
+        // Session time going backwards starts the history again
+        history.record(10.0, &car_update(1, 1, 0, 0.0));
+        assert_eq!(history.samples.len(), 1);
+    }
+
+    #[test]
+    fn race_gaps_and_intervals() {
+        let mut ctx = race_context(&[
+            (1, CupCategory::Overall),
+            (2, CupCategory::Overall),
+            (3, CupCategory::ProAm),
+        ]);
+
+        // Each car covers a tenth of a lap per second, 2s and 5s behind the leader
+        for second in 0..12u16 {
+            let leader = 0.5 + second as f32 * 0.1;
+            let at = |behind: f32| {
+                let p = leader - behind * 0.1;
+                (p.floor() as u16, p.fract())
+            };
+            let (l2, s2) = at(2.0);
+            let (l3, s3) = at(5.0);
+            tick(
+                &mut ctx,
+                second as f32 * 1000.0,
+                &[
+                    (3, 3, l3, s3),
+                    (1, 1, leader.floor() as u16, leader.fract()),
+                    (2, 2, l2, s2),
+                ],
+            );
+        }
+
+        let standings = ctx.standings();
+        assert_eq!(
+            standings.iter().map(|s| s.car_id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(standings[0].position, 1);
+        assert!(standings[0].gap_to_leader.is_none());
+        assert!(standings[0].interval.is_none());
+
+        let secs = |gap: Option<Gap>| gap.and_then(|g| g.time).unwrap().as_secs_f64();
+        assert!((secs(standings[1].gap_to_leader) - 2.0).abs() < 0.01);
+        assert!((secs(standings[2].gap_to_leader) - 5.0).abs() < 0.01);
+        assert!((secs(standings[2].interval) - 3.0).abs() < 0.01);
+        assert!((standings[2].interval.unwrap().laps - 0.3).abs() < 0.001);
+        assert_eq!(standings[2].interval.unwrap().whole_laps(), 0);
+
+        // Car 2 is the leader of its own class, car 3 is alone in its class
+        assert!((secs(standings[1].gap_to_class_leader) - 2.0).abs() < 0.01);
+        assert!(standings[2].gap_to_class_leader.is_none());
+    }
+
+    #[test]
+    fn lapped_cars_fall_back_to_lap_time() {
+        let mut ctx = race_context(&[(1, CupCategory::Overall), (2, CupCategory::Overall)]);
+        tick(&mut ctx, 1000.0, &[(1, 1, 5, 0.5), (2, 2, 3, 0.25)]);
+
+        let mut leader = car_update(1, 1, 5, 0.6);
+        leader.last_lap = lap(1, Some(100_000));
+        ctx.update_car_state(leader);
+
+        let standings = ctx.standings();
+        let gap = standings[1].gap_to_leader.unwrap();
+        assert!((gap.laps - 2.35).abs() < 0.001);
+        assert_eq!(gap.whole_laps(), 2);
+        assert_eq!(gap.time.map(|t| t.as_secs()), Some(235));
+    }
+
+    #[test]
+    fn qualifying_gaps_use_best_laps() {
+        let mut ctx = race_context(&[(1, CupCategory::Overall), (2, CupCategory::Overall)]);
+        ctx.update_session(session_update(
+            SessionType::Qualifying,
+            SessionPhase::Session,
+            1000.0,
+        ));
+
+        let mut first = car_update(1, 1, 3, 0.1);
+        first.best_session_lap = lap(1, Some(90_000));
+        let mut second = car_update(2, 2, 4, 0.8);
+        second.best_session_lap = lap(2, Some(91_250));
+        ctx.update_car_state(second);
+        ctx.update_car_state(first);
+
+        let standings = ctx.standings();
+        let gap = standings[1].gap_to_leader.unwrap();
+        assert_eq!(gap.time, Some(Duration::from_millis(1250)));
+        assert_eq!(gap.laps, 0.0);
+    }
+
+    #[test]
+    fn unplaced_cars_sort_last() {
+        let mut ctx = race_context(&[(1, CupCategory::Overall), (2, CupCategory::Overall)]);
+        ctx.update_car_entry(entry(3, 3, CupCategory::Overall));
+        tick(&mut ctx, 0.0, &[(2, 1, 0, 0.5), (1, 0, 0, 0.1)]);
+
+        let ids: Vec<u16> = ctx.standings().iter().map(|s| s.car_id).collect();
+        assert_eq!(ids, vec![2, 1, 3]);
+    }
+}
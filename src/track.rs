@@ -0,0 +1,372 @@
+//! The circuits available in the simulator, and how to recognise them.
+//!
+//! The Broadcasting API doesn't give tracks a stable identifier, so [`Track::identify`] works from
+//! the name and length sent in [`TrackData`](crate::protocol::inbound::TrackData).
+
+use crate::protocol::acc_enum::Nationality;
+use crate::session::SECTORS;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// A circuit available in the simulator.
+///
+/// The broadcasting protocol only identifies the track by the name and length sent in
+/// [`TrackData`](crate::protocol::inbound::TrackData), use [`Track::identify`] or
+/// [`TrackData::track`](crate::protocol::inbound::TrackData::track) to map those to a variant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Track {
+    Monza,
+    Zolder,
+    BrandsHatch,
+    Silverstone,
+    PaulRicard,
+    Misano,
+    Spa,
+    Nurburgring,
+    Barcelona,
+    Hungaroring,
+    Zandvoort,
+    Kyalami,
+    MountPanorama,
+    Suzuka,
+    LagunaSeca,
+    Imola,
+    OultonPark,
+    Donington,
+    Snetterton,
+    Cota,
+    Indianapolis,
+    WatkinsGlen,
+    Valencia,
+    RedBullRing,
+    Nurburgring24h,
+}
+
+impl Track {
+    /// Every known track.
+    pub const ALL: [Track; 25] = [
+        Track::Monza,
+        Track::Zolder,
+        Track::BrandsHatch,
+        Track::Silverstone,
+        Track::PaulRicard,
+        Track::Misano,
+        Track::Spa,
+        Track::Nurburgring,
+        Track::Barcelona,
+        Track::Hungaroring,
+        Track::Zandvoort,
+        Track::Kyalami,
+        Track::MountPanorama,
+        Track::Suzuka,
+        Track::LagunaSeca,
+        Track::Imola,
+        Track::OultonPark,
+        Track::Donington,
+        Track::Snetterton,
+        Track::Cota,
+        Track::Indianapolis,
+        Track::WatkinsGlen,
+        Track::Valencia,
+        Track::RedBullRing,
+        Track::Nurburgring24h,
+    ];
+
+    /// Details of the track.
+    pub fn info(&self) -> TrackInfo {
+        let (config_key, name, country, length_m, pit_lane_secs) = match self {
+            Track::Monza => ("monza", "Monza Circuit", Nationality::Italy, 5793, 25),
+            Track::Zolder => ("zolder", "Circuit Zolder", Nationality::Belgium, 4011, 26),
+            Track::BrandsHatch => (
+                "brands_hatch",
+                "Brands Hatch Circuit",
+                Nationality::GreatBritain,
+                3908,
+                21,
+            ),
+            Track::Silverstone => (
+                "silverstone",
+                "Silverstone Circuit",
+                Nationality::GreatBritain,
+                5891,
+                24,
+            ),
+            Track::PaulRicard => (
+                "paul_ricard",
+                "Circuit Paul Ricard",
+                Nationality::France,
+                5770,
+                23,
+            ),
+            Track::Misano => (
+                "misano",
+                "Misano World Circuit",
+                Nationality::Italy,
+                4226,
+                25,
+            ),
+            Track::Spa => (
+                "spa",
+                "Circuit de Spa-Francorchamps",
+                Nationality::Belgium,
+                7004,
+                22,
+            ),
+            Track::Nurburgring => ("nurburgring", "Nürburgring", Nationality::Germany, 5137, 24),
+            Track::Barcelona => (
+                "barcelona",
+                "Circuit de Barcelona-Catalunya",
+                Nationality::Spain,
+                4655,
+                22,
+            ),
+            Track::Hungaroring => ("hungaroring", "Hungaroring", Nationality::Hungary, 4381, 23),
+            Track::Zandvoort => (
+                "zandvoort",
+                "Circuit Zandvoort",
+                Nationality::Netherlands,
+                4259,
+                20,
+            ),
+            Track::Kyalami => (
+                "kyalami",
+                "Kyalami Grand Prix Circuit",
+                Nationality::SouthAfrica,
+                4522,
+                23,
+            ),
+            Track::MountPanorama => (
+                "mount_panorama",
+                "Mount Panorama Circuit",
+                Nationality::Australia,
+                6213,
+                27,
+            ),
+            Track::Suzuka => ("suzuka", "Suzuka Circuit", Nationality::Japan, 5807, 25),
+            Track::LagunaSeca => (
+                "laguna_seca",
+                "WeatherTech Raceway Laguna Seca",
+                Nationality::Usa,
+                3602,
+                26,
+            ),
+            Track::Imola => (
+                "imola",
+                "Autodromo Enzo e Dino Ferrari",
+                Nationality::Italy,
+                4959,
+                29,
+            ),
+            Track::OultonPark => (
+                "oulton_park",
+                "Oulton Park",
+                Nationality::GreatBritain,
+                4307,
+                27,
+            ),
+            Track::Donington => (
+                "donington",
+                "Donington Park",
+                Nationality::GreatBritain,
+                4020,
+                24,
+            ),
+            Track::Snetterton => (
+                "snetterton",
+                "Snetterton Circuit",
+                Nationality::GreatBritain,
+                4779,
+                23,
+            ),
+            Track::Cota => (
+                "cota",
+                "Circuit of the Americas",
+                Nationality::Usa,
+                5513,
+                24,
+            ),
+            Track::Indianapolis => (
+                "indianapolis",
+                "Indianapolis Motor Speedway",
+                Nationality::Usa,
+                4167,
+                22,
+            ),
+            Track::WatkinsGlen => (
+                "watkins_glen",
+                "Watkins Glen International",
+                Nationality::Usa,
+                5552,
+                25,
+            ),
+            Track::Valencia => (
+                "valencia",
+                "Circuit Ricardo Tormo",
+                Nationality::Spain,
+                4005,
+                24,
+            ),
+            Track::RedBullRing => (
+                "red_bull_ring",
+                "Red Bull Ring",
+                Nationality::Austria,
+                4318,
+                22,
+            ),
+            Track::Nurburgring24h => (
+                "nurburgring_24h",
+                "Nürburgring 24h",
+                Nationality::Germany,
+                25378,
+                24,
+            ),
+        };
+        TrackInfo {
+            config_key,
+            name,
+            country,
+            length_m,
+            sectors: SECTORS as u8,
+            pit_lane_time: Duration::from_secs(pit_lane_secs),
+        }
+    }
+
+    /// The key used for the track in server and event config files, such as `spa`.
+    pub fn config_key(&self) -> &'static str {
+        self.info().config_key
+    }
+
+    /// Looks up a track by its config file key, accepting the year suffixed keys used by older
+    /// simulator versions such as `spa_2020`.
+    pub fn from_config_key(key: &str) -> Option<Track> {
+        let key = key.trim().to_lowercase();
+        let base = match key.rsplit_once('_') {
+            Some((base, year)) if year.len() == 4 && year.starts_with("20") => base,
+            _ => key.as_str(),
+        };
+        Track::ALL
+            .iter()
+            .copied()
+            .find(|track| track.config_key() == base)
+    }
+
+    /// Identifies a track from the name and distance, in meters, sent in `TrackData`.
+    ///
+    /// The name is matched on keywords, with the distance used to tell apart layouts which share
+    /// a name. If the name isn't recognised, a track is only returned when exactly one has a
+    /// length within a few meters of `distance`.
+    pub fn identify(name: &str, distance: u32) -> Option<Track> {
+        let name = name.to_lowercase();
+        let words: Vec<&str> = name.split(|c: char| !c.is_alphanumeric()).collect();
+        let by_distance =
+            |track: &Track| (i64::from(track.info().length_m) - i64::from(distance)).abs();
+
+        let named = Track::ALL
+            .iter()
+            .copied()
+            .filter(|track| track.keywords().iter().any(|k| words.contains(k)))
+            .min_by_key(by_distance);
+        if named.is_some() {
+            return named;
+        }
+
+        let mut close = Track::ALL
+            .iter()
+            .copied()
+            .filter(|track| by_distance(track) <= 5);
+        match (close.next(), close.next()) {
+            (Some(track), None) => Some(track),
+            _ => None,
+        }
+    }
+
+    // Words which appear in the names the simulator uses for the track
+    fn keywords(&self) -> &'static [&'static str] {
+        match self {
+            Track::Monza => &["monza"],
+            Track::Zolder => &["zolder"],
+            Track::BrandsHatch => &["brands"],
+            Track::Silverstone => &["silverstone"],
+            Track::PaulRicard => &["ricard"],
+            Track::Misano => &["misano"],
+            Track::Spa => &["spa", "francorchamps"],
+            Track::Nurburgring => &["nurburgring", "nürburgring"],
+            Track::Barcelona => &["barcelona", "catalunya"],
+            Track::Hungaroring => &["hungaroring"],
+            Track::Zandvoort => &["zandvoort"],
+            Track::Kyalami => &["kyalami"],
+            Track::MountPanorama => &["panorama", "bathurst"],
+            Track::Suzuka => &["suzuka"],
+            Track::LagunaSeca => &["laguna"],
+            Track::Imola => &["imola", "enzo"],
+            Track::OultonPark => &["oulton"],
+            Track::Donington => &["donington"],
+            Track::Snetterton => &["snetterton"],
+            Track::Cota => &["americas", "cota"],
+            Track::Indianapolis => &["indianapolis"],
+            Track::WatkinsGlen => &["watkins"],
+            Track::Valencia => &["valencia", "tormo"],
+            Track::RedBullRing => &["bull", "spielberg"],
+            Track::Nurburgring24h => &["nurburgring", "nürburgring", "nordschleife"],
+        }
+    }
+}
+
+impl Display for Track {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.info().name)
+    }
+}
+
+/// Static details of a [`Track`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TrackInfo {
+    /// The key used in server and event config files.
+    pub config_key: &'static str,
+    pub name: &'static str,
+    pub country: Nationality,
+    /// Lap length in meters.
+    pub length_m: u32,
+    /// The number of timed sectors, which the simulator makes the same for every track.
+    pub sectors: u8,
+    /// Approximate time lost driving through the pit lane at the speed limit, excluding any
+    /// time spent stationary.
+    pub pit_lane_time: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identify_tracks() {
+        // As sent in docs/pcap/track_data.bin
+        assert_eq!(Track::identify("Circuit Zolder", 4011), Some(Track::Zolder));
+        assert_eq!(
+            Track::identify("Nürburgring", 25378),
+            Some(Track::Nurburgring24h)
+        );
+        assert_eq!(
+            Track::identify("Nurburgring", 5137),
+            Some(Track::Nurburgring)
+        );
+        assert_eq!(Track::identify("Unnamed", 7004), Some(Track::Spa));
+        assert_eq!(Track::identify("Unnamed", 1234), None);
+    }
+
+    #[test]
+    fn track_config_keys() {
+        assert_eq!(Track::from_config_key("spa_2020"), Some(Track::Spa));
+        assert_eq!(
+            Track::from_config_key("nurburgring_24h"),
+            Some(Track::Nurburgring24h)
+        );
+        assert_eq!(Track::from_config_key("le_mans"), None);
+        for track in Track::ALL.iter() {
+            assert_eq!(Track::from_config_key(track.config_key()), Some(*track));
+            assert_eq!(track.info().sectors, 3);
+        }
+    }
+}